@@ -0,0 +1,151 @@
+//! Derive macros for [blather](https://docs.rs/blather).
+//!
+//! This crate should not be used directly; enable the `derive` feature of
+//! `blather` instead, which re-exports the macros.
+
+use proc_macro::TokenStream;
+
+use proc_macro2::TokenStream as TokenStream2;
+
+use quote::quote;
+
+use syn::{
+  parse_macro_input, Data, DeriveInput, Error, Fields, GenericArgument,
+  LitStr, PathArguments, Type
+};
+
+
+/// Derive `blather::FromTelegram` for a struct with named fields.
+///
+/// Each field is read from the telegram parameter with the same name as the
+/// field, and parsed using its `FromStr` implementation.
+///
+/// - Fields of type `Option<T>` are set to `None` if the parameter does not
+///   exist.
+/// - Fields marked with `#[blather(default)]` are set to their type's
+///   `Default` value if the parameter does not exist.
+/// - `#[blather(rename = "Key")]` reads the field from the parameter `Key`.
+///
+/// All other fields are required.
+#[proc_macro_derive(FromTelegram, attributes(blather))]
+pub fn derive_from_telegram(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  match from_telegram(&input) {
+    Ok(ts) => ts.into(),
+    Err(e) => e.to_compile_error().into()
+  }
+}
+
+
+/// Field options given using `#[blather(...)]` attributes.
+#[derive(Default)]
+struct FieldAttrs {
+  rename: Option<String>,
+  default: bool
+}
+
+fn field_attrs(field: &syn::Field) -> Result<FieldAttrs, Error> {
+  let mut attrs = FieldAttrs::default();
+  for attr in field.attrs.iter().filter(|a| a.path().is_ident("blather")) {
+    attr.parse_nested_meta(|meta| {
+      if meta.path.is_ident("rename") {
+        let key: LitStr = meta.value()?.parse()?;
+        attrs.rename = Some(key.value());
+        Ok(())
+      } else if meta.path.is_ident("default") {
+        attrs.default = true;
+        Ok(())
+      } else {
+        Err(meta.error("unsupported blather attribute"))
+      }
+    })?;
+  }
+  Ok(attrs)
+}
+
+/// If `ty` is an `Option<T>`, return `T`.
+fn option_inner(ty: &Type) -> Option<&Type> {
+  let path = match ty {
+    Type::Path(tp) if tp.qself.is_none() => &tp.path,
+    _ => return None
+  };
+  let seg = path.segments.last()?;
+  if seg.ident != "Option" {
+    return None;
+  }
+  match seg.arguments {
+    PathArguments::AngleBracketed(ref args) if args.args.len() == 1 => {
+      match args.args.first() {
+        Some(GenericArgument::Type(inner)) => Some(inner),
+        _ => None
+      }
+    }
+    _ => None
+  }
+}
+
+fn from_telegram(input: &DeriveInput) -> Result<TokenStream2, Error> {
+  let fields = match input.data {
+    Data::Struct(ref data) => match data.fields {
+      Fields::Named(ref fields) => &fields.named,
+      _ => {
+        return Err(Error::new_spanned(
+          input,
+          "FromTelegram requires a struct with named fields"
+        ))
+      }
+    },
+    _ => {
+      return Err(Error::new_spanned(
+        input,
+        "FromTelegram can only be derived for structs"
+      ))
+    }
+  };
+
+  let mut inits = Vec::new();
+  for field in fields {
+    let attrs = field_attrs(field)?;
+    let ident = field.ident.as_ref().expect("named field");
+    let key = attrs.rename.unwrap_or_else(|| ident.to_string());
+    let ty = &field.ty;
+
+    let init = if let Some(inner) = option_inner(ty) {
+      quote! {
+        if params.have(#key) {
+          Some(params.get_param::<#inner>(#key)?)
+        } else {
+          None
+        }
+      }
+    } else if attrs.default {
+      quote! {
+        params.get_param_or::<#ty, _>(#key, ::std::default::Default::default)?
+      }
+    } else {
+      quote! { params.get_param::<#ty>(#key)? }
+    };
+    inits.push(quote! { #ident: #init });
+  }
+
+  let name = &input.ident;
+  let (impl_generics, ty_generics, where_clause) =
+    input.generics.split_for_impl();
+
+  Ok(quote! {
+    impl #impl_generics ::blather::FromTelegram for #name #ty_generics
+    #where_clause
+    {
+      fn from_telegram(
+        tg: &::blather::Telegram
+      ) -> ::std::result::Result<Self, ::blather::Error> {
+        let params: &::blather::Params = ::std::convert::AsRef::as_ref(tg);
+        ::std::result::Result::Ok(#name {
+          #(#inits),*
+        })
+      }
+    }
+  })
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -4,19 +4,59 @@
 use std::fmt;
 use std::fs::File;
 use std::io::Write;
+use std::convert::TryFrom;
 use std::path::PathBuf;
-use std::{cmp, collections::HashMap, mem};
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+use std::{borrow::Cow, cmp, collections::HashMap, mem};
 
-use bytes::{BufMut, Bytes, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 
 use tokio::io;
 
 use tokio_util::codec::Decoder;
 use tokio_util::codec::Encoder;
 
+#[cfg(feature = "hmac")]
+use hmac::{Hmac, Mac};
+#[cfg(feature = "hmac")]
+use sha2::Sha256;
+
+use crate::crc32::Crc32;
 use crate::err::Error;
+use crate::types::lines::{split_kv, split_kv_any_ws};
+use crate::types::validators::validate_topic;
 use crate::{KVLines, Params, Telegram};
 
+/// Key of the integrity trailer line of a telegram.
+const CRC_KEY: &str = "__crc";
+
+/// Key of the authentication trailer line of a telegram.
+#[cfg(feature = "hmac")]
+const HMAC_KEY: &str = "__hmac";
+
+/// Authentication code used for the authentication trailer of a telegram.
+#[cfg(feature = "hmac")]
+type HmacSha256 = Hmac<Sha256>;
+
+/// Default maximum length of a received length-prefixed telegram.
+const DEFAULT_MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// UTF-8 encoded byte order mark.
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Callback which receives the chunks of a [`Codec::expect_sink()`]
+/// transfer.
+///
+/// Callbacks are only required to be `Send`.  They are wrapped in a `Mutex`
+/// to keep the `Codec` `Sync`, but are only ever accessed through
+/// `Mutex::get_mut()`, which does not lock.
+type Sink = Mutex<Box<dyn FnMut(&[u8]) + Send>>;
+
+/// Callback which is called for each decoded [`Telegram`].  Set using
+/// [`Codec::set_on_telegram()`].
+type TelegramObserver = Mutex<Box<dyn FnMut(&Telegram) + Send>>;
+
 
 /// Current state of decoder.
 ///
@@ -29,13 +69,24 @@ enum CodecState {
   /// Read and decode a [`Params`] buffer from the network.
   Params,
 
+  /// Read and decode a fixed number of key/value lines into a [`Params`]
+  /// buffer.
+  ParamsCount,
+
   /// Read and decode an vector of key/value pairs.
   KVLines,
 
+  /// Read a topic line followed by an vector of key/value pairs.
+  TopicKVLines,
+
   /// Read a specified amount of raw bytes, and return it in chunks as they
   /// arrive.
   Chunks,
 
+  /// Read raw bytes, and return them in chunks as they arrive, until the
+  /// underlying stream is closed.
+  RawChunks,
+
   /// Read a specified amount of raw bytes, and return the entire immutable
   /// buffer when it has arrived.
   Bytes,
@@ -44,6 +95,10 @@ enum CodecState {
   /// buffer when it has arrived.
   BytesMut,
 
+  /// Read a specified amount of raw bytes, and return the entire buffer as a
+  /// vector when it has arrived.
+  Vec,
+
   /// Read a specified amount of raw bytes and store them in chunks as they
   /// arrive in a file.
   File,
@@ -52,12 +107,146 @@ enum CodecState {
   /// arrive to a writer object.
   Writer,
 
+  /// Read a specified amount of raw bytes, write them in chunks as they
+  /// arrive to a writer object, and return the entire immutable buffer when
+  /// it has arrived.
+  Tee,
+
+  /// Read a specified amount of raw bytes and pass them in chunks as they
+  /// arrive to a callback.
+  Sink,
+
   /// Ignore a specified amount of raw bytes.
-  Skip
+  Skip,
+
+  /// Ignore everything up to, and including, the next newline.
+  SkipLine,
+
+  /// Read a single line and return it as a string.
+  Greeting,
+
+  /// Read a specified amount of raw bytes and decode them as a JSON object.
+  #[cfg(feature = "json")]
+  Json
+}
+
+/// What the decoder expects to receive next, as reported by
+/// [`Codec::mode()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+  /// Expecting a [`Telegram`].
+  Telegram,
+
+  /// Expecting a [`Params`] buffer.
+  Params,
+
+  /// Expecting a [`KVLines`] buffer.
+  KVLines,
+
+  /// Expecting a topic line followed by a [`KVLines`] buffer.
+  TopicKVLines,
+
+  /// Receiving a raw buffer, returned in chunks.
+  Chunks,
+
+  /// Receiving raw data, returned in chunks, until the end of the stream.
+  RawChunks,
+
+  /// Receiving a raw buffer, returned as a [`Bytes`].
+  Bytes,
+
+  /// Receiving a raw buffer, returned as a [`BytesMut`].
+  BytesMut,
+
+  /// Receiving a raw buffer, returned as a `Vec<u8>`.
+  Vec,
+
+  /// Receiving a raw buffer, stored in a file.
+  File,
+
+  /// Receiving a raw buffer, written to a writer.
+  Writer,
+
+  /// Receiving a raw buffer, written to a writer and returned as a
+  /// [`Bytes`].
+  Tee,
+
+  /// Receiving a raw buffer, passed to a callback.
+  Sink,
+
+  /// Skipping raw data.
+  Skip,
+
+  /// Skipping a single line.
+  SkipLine,
+
+  /// Expecting a single greeting line.
+  Greeting,
+
+  /// Receiving a JSON object, returned as a [`Params`] buffer.
+  #[cfg(feature = "json")]
+  Json
+}
+
+impl From<&CodecState> for Mode {
+  fn from(state: &CodecState) -> Self {
+    match state {
+      CodecState::Telegram => Mode::Telegram,
+      CodecState::Params | CodecState::ParamsCount => Mode::Params,
+      CodecState::KVLines => Mode::KVLines,
+      CodecState::TopicKVLines => Mode::TopicKVLines,
+      CodecState::Chunks => Mode::Chunks,
+      CodecState::RawChunks => Mode::RawChunks,
+      CodecState::Bytes => Mode::Bytes,
+      CodecState::BytesMut => Mode::BytesMut,
+      CodecState::Vec => Mode::Vec,
+      CodecState::File => Mode::File,
+      CodecState::Writer => Mode::Writer,
+      CodecState::Tee => Mode::Tee,
+      CodecState::Sink => Mode::Sink,
+      CodecState::Skip => Mode::Skip,
+      CodecState::SkipLine => Mode::SkipLine,
+      CodecState::Greeting => Mode::Greeting,
+      #[cfg(feature = "json")]
+      CodecState::Json => Mode::Json
+    }
+  }
+}
+
+/// How [`Telegram`]s are delimited on the wire.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Framing {
+  /// Telegrams are terminated by an empty line.  This is the default.
+  #[default]
+  Lines,
+
+  /// Each telegram is prefixed by its length, in bytes, as a 4 byte
+  /// big-endian unsigned integer.  The length covers the telegram's lines,
+  /// including the terminating empty line.  The terminating empty line is
+  /// optional when decoding.  The length of received telegrams is limited by
+  /// [`Codec::set_max_frame_len()`].
+  LengthPrefixed
+}
+
+/// The line which terminates a [`Telegram`], [`Params`] or [`KVLines`]
+/// block on the wire.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Terminator {
+  /// Blocks are terminated by an empty line.  This is the default.
+  #[default]
+  BlankLine,
+
+  /// Blocks are terminated by a line consisting of a single `.` character,
+  /// SMTP-style.  Empty lines within a block are ignored when decoding.
+  ///
+  /// No escaping is needed, since key/value lines always contain a space
+  /// character, and thus can never consist of a single `.`.
+  DotLine
 }
 
 /// Data returned to the application when the Codec's Decode iterator is
 /// called and the decoder has a complete entity to return.
+#[derive(Debug)]
 pub enum Input {
   /// A complete [`Telegram`] has been received.
   Telegram(Telegram),
@@ -65,6 +254,10 @@ pub enum Input {
   /// A complete key/value lines buffer ([`KVLines`]) has been received.
   KVLines(KVLines),
 
+  /// A topic line followed by a complete key/value lines buffer
+  /// ([`KVLines`]) has been received.
+  TopicKVLines(String, KVLines),
+
   /// A complete [`Params`] has been received.
   Params(Params),
 
@@ -73,12 +266,24 @@ pub enum Input {
   /// the `usize` parameter is 0 it means this is the final chunk.
   Chunk(BytesMut, usize),
 
+  /// A chunk of raw data has arrived, while reading raw data until the end of
+  /// the stream.
+  RawChunk(BytesMut),
+
+  /// A malformed telegram has been received and discarded.  Only returned
+  /// if resynchronization has been enabled using
+  /// [`Codec::set_resync_on_error()`].
+  DecodeError(Error),
+
   /// A complete raw immutable buffer has been received.
   Bytes(Bytes),
 
   /// A complete raw mutable buffer has been received.
   BytesMut(BytesMut),
 
+  /// A complete raw buffer has been received as a vector.
+  Vec(Vec<u8>),
+
   /// A complete buffer has been received and stored to the file specified in
   /// `PathBuf`.
   File(PathBuf),
@@ -86,8 +291,72 @@ pub enum Input {
   /// A complete buffer has been written to the writer.
   WriteDone,
 
+  /// A complete buffer has been passed to the sink callback.
+  SinkDone,
+
   /// The requested number of bytes have been ignored.
-  SkipDone
+  SkipDone,
+
+  /// A single line has been ignored.
+  SkipLineDone,
+
+  /// A greeting line has been received.  The string does not include the
+  /// line ending.
+  Greeting(String)
+}
+
+impl Input {
+  /// Return the name of the variant, without its data.  Useful for concise
+  /// logging.
+  ///
+  /// ```
+  /// use blather::{codec::Input, Telegram};
+  ///
+  /// let input = Input::Telegram(Telegram::new_topic("Hello").unwrap());
+  /// assert_eq!(input.variant_name(), "Telegram");
+  /// ```
+  pub fn variant_name(&self) -> &'static str {
+    match self {
+      Input::Telegram(_) => "Telegram",
+      Input::KVLines(_) => "KVLines",
+      Input::TopicKVLines(_, _) => "TopicKVLines",
+      Input::Params(_) => "Params",
+      Input::Chunk(_, _) => "Chunk",
+      Input::RawChunk(_) => "RawChunk",
+      Input::DecodeError(_) => "DecodeError",
+      Input::Bytes(_) => "Bytes",
+      Input::BytesMut(_) => "BytesMut",
+      Input::Vec(_) => "Vec",
+      Input::File(_) => "File",
+      Input::WriteDone => "WriteDone",
+      Input::SinkDone => "SinkDone",
+      Input::SkipDone => "SkipDone",
+      Input::SkipLineDone => "SkipLineDone",
+      Input::Greeting(_) => "Greeting"
+    }
+  }
+}
+
+
+/// Cumulative decoder statistics, as returned by [`Codec::stats()`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CodecStats {
+  /// Number of [`Telegram`]s decoded.
+  pub telegrams: u64,
+
+  /// Number of [`Params`] buffers decoded, including JSON objects.
+  pub params: u64,
+
+  /// Number of [`KVLines`] buffers decoded.
+  pub kvlines: u64,
+
+  /// Number of raw bytes received while the decoder was expecting binary
+  /// data, including skipped bytes.
+  pub binary_bytes: u64,
+
+  /// Number of decoding errors, including malformed telegrams which were
+  /// discarded due to resynchronization.
+  pub errors: u64
 }
 
 
@@ -99,11 +368,52 @@ pub struct Codec {
   tg: Telegram,
   params: Params,
   kvlines: KVLines,
+  kvlines_topic: Option<String>,
   state: CodecState,
   bin_remain: usize,
   pathname: Option<PathBuf>,
   writer: Option<Box<dyn Write + Send + Sync>>,
-  buf: BytesMut
+  sink: Option<Sink>,
+  buf: BytesMut,
+  yield_after: usize,
+  yield_count: usize,
+  integrity: bool,
+  crc: Crc32,
+  crc_trailer: Option<u32>,
+  #[cfg(feature = "hmac")]
+  hmac_key: Option<HmacSha256>,
+  #[cfg(feature = "hmac")]
+  hmac: Option<HmacSha256>,
+  #[cfg(feature = "hmac")]
+  hmac_trailer: Option<Vec<u8>>,
+  trim_values: bool,
+  framing: Framing,
+  allow_comments: bool,
+  resync_on_error: bool,
+  resyncing: bool,
+  resync_line_empty: bool,
+  strip_bom: bool,
+  bom_checked: bool,
+  reject_duplicate_keys: bool,
+  transfer_size: usize,
+  transfer_started_at: Option<Instant>,
+  max_value_len: usize,
+  separator_any_whitespace: bool,
+  allow_continuations: bool,
+  last_key: Option<String>,
+  max_params: usize,
+  max_frame_len: usize,
+  skip_leading_blanks: bool,
+  max_file_size: usize,
+  terminator: Terminator,
+  params_remain: usize,
+  strict_binary_alignment: bool,
+  check_alignment: bool,
+  compact_topic: bool,
+  paused: bool,
+  repeated_key_join: Option<char>,
+  on_telegram: Option<TelegramObserver>,
+  stats: CodecStats
 }
 
 impl fmt::Debug for Codec {
@@ -152,11 +462,52 @@ impl Codec {
       tg: Telegram::new(),
       params: Params::new(),
       kvlines: KVLines::new(),
+      kvlines_topic: None,
       state: CodecState::Telegram,
       bin_remain: 0,
       pathname: None,
       writer: None,
-      buf: BytesMut::new()
+      sink: None,
+      buf: BytesMut::new(),
+      yield_after: 0,
+      yield_count: 0,
+      integrity: false,
+      crc: Crc32::new(),
+      crc_trailer: None,
+      #[cfg(feature = "hmac")]
+      hmac_key: None,
+      #[cfg(feature = "hmac")]
+      hmac: None,
+      #[cfg(feature = "hmac")]
+      hmac_trailer: None,
+      trim_values: false,
+      framing: Framing::Lines,
+      allow_comments: false,
+      resync_on_error: false,
+      resyncing: false,
+      resync_line_empty: true,
+      strip_bom: false,
+      bom_checked: false,
+      reject_duplicate_keys: false,
+      transfer_size: 0,
+      transfer_started_at: None,
+      max_value_len: usize::MAX,
+      separator_any_whitespace: false,
+      allow_continuations: false,
+      last_key: None,
+      max_params: usize::MAX,
+      max_frame_len: DEFAULT_MAX_FRAME_LEN,
+      skip_leading_blanks: false,
+      max_file_size: usize::MAX,
+      terminator: Terminator::BlankLine,
+      params_remain: 0,
+      strict_binary_alignment: false,
+      check_alignment: false,
+      compact_topic: false,
+      paused: false,
+      repeated_key_join: None,
+      on_telegram: None,
+      stats: CodecStats::default()
     }
   }
 
@@ -174,6 +525,462 @@ impl Codec {
     self.max_line_length
   }
 
+  /// Get what the decoder expects to receive next.
+  ///
+  /// Once a buffer requested using one of the `expect_*()` methods has been
+  /// completely received, the decoder reverts to [`Mode::Telegram`].
+  pub fn mode(&self) -> Mode {
+    Mode::from(&self.state)
+  }
+
+  /// Get the cumulative statistics of the decoder.
+  pub fn stats(&self) -> CodecStats {
+    self.stats
+  }
+
+  /// Returns `true` if the decoder expects to receive a [`Telegram`] next.
+  pub fn is_expecting_telegram(&self) -> bool {
+    self.state == CodecState::Telegram
+  }
+
+  /// Limit the number of frames the decoder will return in a row before it
+  /// forces the `Framed` reader back to the underlying reader.  Setting the
+  /// limit to `0` (the default) disables the limit.
+  ///
+  /// The `Decoder` only returns a single frame per call, but the `Framed`
+  /// reader will keep calling it for as long as complete frames are
+  /// available in its buffer.  A connection with many pipelined frames can
+  /// thus monopolize a task.  When `count` frames have been returned the
+  /// decoder will return `Ok(None)` once, which causes the `Framed` reader to
+  /// poll the underlying reader, giving the runtime an opportunity to
+  /// schedule other tasks.
+  ///
+  /// # Notes
+  /// - When the decoder yields, any complete frames remaining in the buffer
+  ///   will not be returned until the underlying reader has received more
+  ///   data or reached end-of-stream.  This makes this option unsuitable for
+  ///   protocols where the peer waits for a reply before sending more data.
+  pub fn set_yield_after(&mut self, count: usize) {
+    self.yield_after = count;
+    self.yield_count = 0;
+  }
+
+  /// Pause the decoder.  While paused the decoder returns `Ok(None)`, even if
+  /// complete frames are available in the input buffer, which stalls the
+  /// `Framed` reader's stream.
+  ///
+  /// This can be used to stop receiving chunks while a downstream consumer
+  /// is unable to keep up.  Call [`Codec::resume()`] to continue decoding.
+  ///
+  /// # Notes
+  /// - The `Framed` reader will continue to read data from the underlying
+  ///   reader into its buffer while the decoder is paused, so the
+  ///   application should not remain paused indefinitely.
+  /// - End-of-stream processing is not affected by the pause, since the
+  ///   `Framed` reader would otherwise report the buffered data as an error.
+  /// - Once the decoder has returned `Ok(None)` the `Framed` reader will not
+  ///   call it again until more data has arrived.  See [`Codec::resume()`]
+  ///   for how to receive frames which were buffered while paused.
+  pub fn pause(&mut self) {
+    self.paused = true;
+  }
+
+  /// Resume a decoder which has been paused using [`Codec::pause()`].
+  ///
+  /// If the `Framed` stream was polled while the decoder was paused, the
+  /// `Framed` reader will wait for more data from the underlying reader
+  /// before it calls the decoder again, even if complete frames are already
+  /// buffered.  For protocols where the peer waits for a reply, the
+  /// application must drive the decoder over the buffered data itself after
+  /// resuming, until it returns `Ok(None)`:
+  ///
+  /// ```no_run
+  /// use tokio::net::TcpStream;
+  /// use tokio_util::codec::{Decoder, Framed};
+  /// use blather::{Codec, Error};
+  ///
+  /// fn resume(frm: &mut Framed<TcpStream, Codec>) -> Result<(), Error> {
+  ///   frm.codec_mut().resume();
+  ///   loop {
+  ///     let mut buf = frm.read_buffer_mut().split();
+  ///     let input = frm.codec_mut().decode(&mut buf);
+  ///     *frm.read_buffer_mut() = buf;
+  ///     match input? {
+  ///       Some(input) => println!("{}", input.variant_name()),
+  ///       None => break
+  ///     }
+  ///   }
+  ///   Ok(())
+  /// }
+  /// ```
+  pub fn resume(&mut self) {
+    self.paused = false;
+  }
+
+  /// Returns `true` if the decoder has been paused.
+  pub fn is_paused(&self) -> bool {
+    self.paused
+  }
+
+  /// Register a callback which is called with each [`Telegram`] the decoder
+  /// has received, just before it is returned to the application.  This
+  /// replaces any previously registered callback.
+  ///
+  /// This is intended for observability, such as counting received
+  /// telegrams per topic.
+  ///
+  /// ```
+  /// use std::sync::atomic::{AtomicUsize, Ordering};
+  /// use std::sync::Arc;
+  /// use blather::Codec;
+  ///
+  /// let count = Arc::new(AtomicUsize::new(0));
+  /// let mut codec = Codec::new();
+  /// let c = Arc::clone(&count);
+  /// codec.set_on_telegram(move |_tg| {
+  ///   c.fetch_add(1, Ordering::Relaxed);
+  /// });
+  /// ```
+  pub fn set_on_telegram<F>(&mut self, f: F)
+  where
+    F: 'static + FnMut(&Telegram) + Send
+  {
+    self.on_telegram = Some(Mutex::new(Box::new(f)));
+  }
+
+  /// Remove a callback registered using [`Codec::set_on_telegram()`].
+  pub fn clear_on_telegram(&mut self) {
+    self.on_telegram = None;
+  }
+
+  /// Enable or disable telegram integrity checks.
+  ///
+  /// When enabled, the encoder will append a `__crc <hex>` line, containing
+  /// the CRC-32 of all the preceding lines of the telegram, just before the
+  /// terminating empty line.  The decoder will require each received
+  /// telegram to end with such a trailer line, verify the checksum and strip
+  /// the trailer from the returned [`Telegram`].  A missing trailer, a
+  /// checksum mismatch or lines following the trailer cause the decoder to
+  /// return `Error::BadFormat`.
+  ///
+  /// Both peers must agree on whether integrity checks are used.  Only
+  /// telegrams are affected; other buffers are sent and received as-is.
+  pub fn set_integrity(&mut self, enable: bool) {
+    self.integrity = enable;
+  }
+
+
+  /// Enable telegram authentication using the shared secret `key`.
+  ///
+  /// When enabled, the encoder will append a `__hmac <hex>` line, containing
+  /// the HMAC-SHA256 of all the preceding lines of the telegram, just before
+  /// the terminating empty line (and before the integrity trailer, if
+  /// enabled).  The decoder will require each received telegram to end with
+  /// such a trailer line, verify it using `key` and strip the trailer from
+  /// the returned [`Telegram`].  A missing trailer, an authentication code
+  /// mismatch or lines following the trailer cause the decoder to return
+  /// `Error::BadFormat`.
+  ///
+  /// Both peers must use the same key.  Only telegrams are affected; other
+  /// buffers are sent and received as-is.
+  #[cfg(feature = "hmac")]
+  pub fn set_hmac_key<K: AsRef<[u8]>>(&mut self, key: K) {
+    // HMAC accepts keys of any length.
+    let mac = HmacSha256::new_from_slice(key.as_ref())
+      .expect("HMAC key of invalid length");
+    self.hmac_key = Some(mac);
+  }
+
+  /// Disable telegram authentication enabled using
+  /// [`Codec::set_hmac_key()`].
+  #[cfg(feature = "hmac")]
+  pub fn clear_hmac_key(&mut self) {
+    self.hmac_key = None;
+  }
+
+
+  /// Trim leading and trailing ASCII whitespace from values of key/value
+  /// lines when decoding [`Telegram`]s, [`Params`] and [`KVLines`].  Keys are
+  /// not affected.
+  ///
+  /// This is disabled by default, in order to preserve values exactly as
+  /// they were sent.
+  pub fn set_trim_values(&mut self, enable: bool) {
+    self.trim_values = enable;
+  }
+
+
+  /// Select how [`Telegram`]s are delimited, both when encoding and when
+  /// decoding.  Other buffers are not affected.
+  ///
+  /// See [`Framing`] for details.
+  pub fn set_framing(&mut self, framing: Framing) {
+    self.framing = framing;
+  }
+
+
+  /// Set the maximum length, in bytes, of a received [`Telegram`] when using
+  /// [`Framing::LengthPrefixed`].  If a length prefix announces a longer
+  /// telegram the decoder returns an `Error::LimitExceeded`, without
+  /// allocating space for it.
+  ///
+  /// Defaults to 16 MiB.
+  pub fn set_max_frame_len(&mut self, max: usize) {
+    self.max_frame_len = max;
+  }
+
+
+  /// Allow comment lines when decoding [`Telegram`]s, [`Params`] and
+  /// [`KVLines`].  When enabled, lines beginning with a `#` character are
+  /// ignored.  An empty line still marks the end of a buffer.
+  ///
+  /// This is disabled by default.
+  pub fn set_allow_comments(&mut self, enable: bool) {
+    self.allow_comments = enable;
+  }
+
+
+  /// Enable or disable recovery from malformed telegrams.
+  ///
+  /// Normally an `Error::BadFormat` while decoding a telegram terminates the
+  /// `Framed` stream.  When resynchronization is enabled, the decoder will
+  /// instead return an [`Input::DecodeError(err)`](Input::DecodeError),
+  /// discard the rest of the malformed telegram (up to and including the
+  /// next empty line) and then continue decoding telegrams.
+  ///
+  /// Only `Error::BadFormat` errors and lines which are not valid UTF-8
+  /// while expecting telegrams are recovered from.
+  pub fn set_resync_on_error(&mut self, enable: bool) {
+    self.resync_on_error = enable;
+  }
+
+
+  /// Control whether a UTF-8 byte order mark (`EF BB BF`) at the very start
+  /// of the input stream is discarded.
+  ///
+  /// The byte order mark is only stripped if the decoder is expecting a
+  /// line-based buffer when the first data arrives.
+  ///
+  /// This is disabled by default, in which case a leading byte order mark is
+  /// treated as part of the first line.
+  pub fn set_strip_bom(&mut self, enable: bool) {
+    self.strip_bom = enable;
+  }
+
+
+  /// Select the line which terminates [`Telegram`], [`Params`] and
+  /// [`KVLines`] blocks.  This applies to both the encoder and the decoder.
+  ///
+  /// See [`Terminator`] for details.
+  pub fn set_block_terminator(&mut self, terminator: Terminator) {
+    self.terminator = terminator;
+  }
+
+
+  /// Accept a compact telegram format where the topic line may carry the
+  /// first parameter, separated from the topic by a `;` character, as in
+  /// `Topic;key value`.
+  ///
+  /// This only affects the decoder; the encoder always writes the topic on a
+  /// line of its own.  An empty segment after the `;` is ignored.
+  ///
+  /// This is disabled by default.
+  pub fn set_compact_topic(&mut self, enable: bool) {
+    self.compact_topic = enable;
+  }
+
+
+  /// Ignore empty lines preceding a [`Telegram`]'s topic line.
+  ///
+  /// Normally an empty line terminates a telegram, even if no topic has been
+  /// received, which causes a telegram without a topic to be returned.  When
+  /// enabled, empty lines are skipped until a topic line has been received.
+  ///
+  /// This is disabled by default.
+  pub fn set_skip_leading_blanks(&mut self, enable: bool) {
+    self.skip_leading_blanks = enable;
+  }
+
+
+  /// Set the maximum size of a file received using
+  /// [`Codec::expect_file()`].  Requesting a larger file causes
+  /// `expect_file()` to fail with an `Error::InvalidSize`.
+  ///
+  /// Not limited by default.
+  pub fn set_max_file_size(&mut self, max: usize) {
+    self.max_file_size = max;
+  }
+
+
+  /// Require that the data immediately following a completed
+  /// [`Codec::expect_bytes()`], [`Codec::expect_bytesmut()`],
+  /// [`Codec::expect_vec()`], [`Codec::expect_file()`],
+  /// [`Codec::expect_writer()`], [`Codec::expect_tee()`] or
+  /// [`Codec::expect_sink()`] transfer begins
+  /// with a valid telegram topic character.  If it does not, the decoder
+  /// returns an `Error::BadFormat`.
+  ///
+  /// This is useful to detect peers which announce a binary transfer size
+  /// which does not match the amount of data they actually send.
+  ///
+  /// # Notes
+  /// - The check is only performed if the decoder is expecting a
+  ///   [`Telegram`] once the following data arrives.
+  /// - Only the first byte is checked.  Non-ASCII bytes are accepted, and are
+  ///   left to the topic validation.
+  ///
+  /// This is disabled by default.
+  pub fn set_strict_binary_alignment(&mut self, enable: bool) {
+    self.strict_binary_alignment = enable;
+  }
+
+
+  /// Set the maximum number of key/value entries in a received
+  /// [`Telegram`], [`Params`] or [`KVLines`] buffer.  If a buffer contains
+  /// more entries the decoder returns an `Error::LimitExceeded`.
+  ///
+  /// Not limited by default.
+  pub fn set_max_params(&mut self, max: usize) {
+    self.max_params = max;
+  }
+
+
+  /// Set the maximum length, in bytes, of a value in a key/value line of a
+  /// [`Telegram`], [`Params`] or [`KVLines`] buffer.  If a received value
+  /// exceeds this length the decoder returns an `Error::LimitExceeded`
+  /// naming the offending key.
+  ///
+  /// This is independent of the maximum line length, and is not limited by
+  /// default.
+  pub fn set_max_value_len(&mut self, max: usize) {
+    self.max_value_len = max;
+  }
+
+
+  /// Accept either a space or a tab character as the separator between keys
+  /// and values when decoding key/value lines.  When enabled, lines are split
+  /// at the first space or tab character, and the rest of the line is kept
+  /// verbatim as the value.
+  ///
+  /// This is disabled by default, in which case only a space character is
+  /// used as a separator.
+  pub fn set_separator_any_whitespace(&mut self, enable: bool) {
+    self.separator_any_whitespace = enable;
+  }
+
+
+  /// Allow values to be continued on the following lines, RFC822-style.
+  ///
+  /// When enabled, a line beginning with a space or tab character is not
+  /// parsed as a key/value line.  Instead its leading whitespace is removed
+  /// and the rest of the line is appended, separated by a single space
+  /// character, to the value of the preceding key/value line.  A
+  /// continuation line without a preceding key/value line causes an
+  /// `Error::BadFormat`.
+  ///
+  /// This applies to [`Telegram`]s, [`Params`] and [`KVLines`], and is
+  /// disabled by default.
+  pub fn set_allow_continuations(&mut self, enable: bool) {
+    self.allow_continuations = enable;
+  }
+
+
+  /// Join the values of keys which occur more than once in a received
+  /// [`Telegram`] or [`Params`] buffer, using the separator `sep`, instead of
+  /// letting the later value overwrite the earlier one.  Passing `None`
+  /// restores the default overwriting behavior.
+  ///
+  /// For instance, with a `,` separator the lines `tag a`, `tag b` and
+  /// `tag c` are decoded as a single parameter `tag` with the value `a,b,c`,
+  /// which can be read using [`Params::get_strvec()`].
+  ///
+  /// # Notes
+  /// - Rejecting duplicate keys, using
+  ///   [`Codec::set_reject_duplicate_keys()`], takes precedence.
+  /// - The maximum value length applies to the joined value.
+  /// - [`KVLines`] buffers are not affected, since they allow repeated keys
+  ///   by design.
+  pub fn set_repeated_key_join(&mut self, sep: Option<char>) {
+    self.repeated_key_join = sep;
+  }
+
+
+  /// Reject telegrams and [`Params`] buffers that contain the same key more
+  /// than once.  When enabled, the decoder returns an `Error::BadFormat`
+  /// naming the duplicated key instead of letting the later value overwrite
+  /// the earlier one.
+  ///
+  /// This is disabled by default.  [`KVLines`] buffers are not affected,
+  /// since they allow repeated keys by design.
+  pub fn set_reject_duplicate_keys(&mut self, enable: bool) {
+    self.reject_duplicate_keys = enable;
+  }
+
+  /// Return the point in time when the current binary transfer was
+  /// initiated, or `None` if the decoder isn't currently expecting binary
+  /// data.
+  ///
+  /// The codec itself does not enforce any timeouts, but a supervising task
+  /// can use this, together with [`Codec::transfer_bytes_received()`], to
+  /// detect a stalled transfer.
+  ///
+  /// # Notes
+  /// - The start time is recorded when one of the binary `expect_*` methods
+  ///   (or [`Codec::skip()`]) is called, and it is cleared once the transfer
+  ///   has completed.
+  pub fn transfer_started_at(&self) -> Option<Instant> {
+    self.transfer_started_at
+  }
+
+  /// Return the number of bytes that have been received so far in the
+  /// current binary transfer, or `None` if the decoder isn't currently
+  /// expecting binary data.
+  pub fn transfer_bytes_received(&self) -> Option<usize> {
+    self
+      .transfer_started_at
+      .map(|_| self.transfer_size - self.bin_remain)
+  }
+
+  /// Record the start of a binary transfer of `size` bytes.
+  fn begin_transfer(&mut self, size: usize) {
+    self.bin_remain = size;
+    self.transfer_size = size;
+    self.transfer_started_at = Some(Instant::now());
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+      size,
+      mode = ?Mode::from(&self.state),
+      "Binary transfer started"
+    );
+  }
+
+  /// Clear the binary transfer timing information once the decoder is no
+  /// longer expecting binary data.
+  fn update_transfer(&mut self) {
+    match self.state {
+      CodecState::Telegram
+      | CodecState::Params
+      | CodecState::ParamsCount
+      | CodecState::KVLines
+      | CodecState::TopicKVLines
+      | CodecState::SkipLine
+      | CodecState::Greeting
+      | CodecState::RawChunks => {
+        #[cfg(feature = "tracing")]
+        if self.transfer_started_at.is_some() {
+          tracing::debug!(
+            bytes = self.transfer_size - self.bin_remain,
+            "Binary transfer ended"
+          );
+        }
+
+        self.transfer_size = 0;
+        self.transfer_started_at = None;
+      }
+      _ => {}
+    }
+  }
 
   /// Determine how far into the buffer we'll search for a newline. If
   /// there's no max_length set, we'll read to the end of the buffer.
@@ -195,25 +1002,299 @@ impl Codec {
   /// Following lines are parameter lines, which are a single space character
   /// separated key/value pairs.
   fn decode_telegram_line(&mut self, line: &str) -> Result<(), Error> {
+    if self.is_comment(line) {
+      return Ok(());
+    }
+
     if self.tg.get_topic().is_none() {
-      self.tg.set_topic(line)?;
+      let (topic, first) = match line.split_once(';') {
+        Some((topic, first)) if self.compact_topic => (topic, first),
+        _ => (line, "")
+      };
+      self.tg.set_topic(topic)?;
+      self.last_key = None;
+      if self.integrity {
+        self.crc = Crc32::new();
+        self.crc_trailer = None;
+        self.crc.update(line.as_bytes());
+        self.crc.update(b"\n");
+      }
+      #[cfg(feature = "hmac")]
+      self.start_hmac(line);
+      if !first.is_empty() {
+        let (k, v) = match self.split_line(first)? {
+          Some(kv) => kv,
+          None => {
+            return Err(Error::BadFormat(
+              "Invalid compact topic parameter".to_string()
+            ))
+          }
+        };
+        self.check_num_params(self.tg.get_params(), k)?;
+        self.tg.add_param(k, v)?;
+        if self.allow_continuations {
+          self.last_key = Some(k.to_string());
+        }
+      }
     } else {
-      let idx = line.find(' ');
-      if let Some(idx) = idx {
-        let (k, v) = line.split_at(idx);
-        let v = &v[1..v.len()];
+      if self.integrity {
+        if self.crc_trailer.is_some() {
+          return Err(Error::BadFormat(
+            "Unexpected data after integrity trailer".to_string()
+          ));
+        }
+        if let Some(hex) = line.strip_prefix(CRC_KEY) {
+          if let Some(hex) = hex.strip_prefix(' ') {
+            let crc = u32::from_str_radix(hex, 16).map_err(|_| {
+              Error::BadFormat("Invalid integrity trailer".to_string())
+            })?;
+            self.crc_trailer = Some(crc);
+            return Ok(());
+          }
+        }
+        self.crc.update(line.as_bytes());
+        self.crc.update(b"\n");
+      }
+      #[cfg(feature = "hmac")]
+      if self.update_hmac(line)? {
+        return Ok(());
+      }
+      if let Some(cont) = self.continuation(line) {
+        let (k, v) = self.continue_value(self.tg.get_params(), cont)?;
         self.tg.add_param(k, v)?;
+      } else if let Some((k, v)) = self.split_line(line)? {
+        self.check_duplicate(self.tg.get_params(), k)?;
+        self.check_num_params(self.tg.get_params(), k)?;
+        let v = self.join_repeated(self.tg.get_params(), k, v)?;
+        self.tg.add_param(k, v)?;
+        if self.allow_continuations {
+          self.last_key = Some(k.to_string());
+        }
       }
     }
     Ok(())
   }
 
+  /// Returns an error if adding `key` to `params` would exceed the maximum
+  /// number of parameters.
+  fn check_num_params(&self, params: &Params, key: &str) -> Result<(), Error> {
+    if params.len() >= self.max_params && !params.have(key) {
+      return Err(Error::LimitExceeded(format!(
+        "More than {} parameters",
+        self.max_params
+      )));
+    }
+    Ok(())
+  }
+
+  /// Returns an error if duplicate keys are rejected and `key` already
+  /// exists in `params`.
+  fn check_duplicate(&self, params: &Params, key: &str) -> Result<(), Error> {
+    if self.reject_duplicate_keys && params.have(key) {
+      return Err(Error::BadFormat(format!("Duplicate key '{}'", key)));
+    }
+    Ok(())
+  }
+
+  /// Return the value to store for `key`, joining it with an existing value
+  /// in `params` if repeated keys are joined.
+  fn join_repeated<'a>(
+    &self,
+    params: &Params,
+    key: &str,
+    value: &'a str
+  ) -> Result<Cow<'a, str>, Error> {
+    let sep = match self.repeated_key_join {
+      Some(sep) => sep,
+      None => return Ok(Cow::Borrowed(value))
+    };
+    match params.get_str(key) {
+      Some(existing) => {
+        let mut joined = String::with_capacity(
+          existing.len() + sep.len_utf8() + value.len()
+        );
+        joined.push_str(existing);
+        joined.push(sep);
+        joined.push_str(value);
+        self.check_value_len(key, &joined)?;
+        Ok(Cow::Owned(joined))
+      }
+      None => Ok(Cow::Borrowed(value))
+    }
+  }
+
+  /// Returns `true` if `line`, excluding its line ending, terminates a block.
+  fn is_terminator(&self, line: &[u8]) -> bool {
+    match self.terminator {
+      Terminator::BlankLine => line.is_empty(),
+      Terminator::DotLine => line == b"."
+    }
+  }
+
+  /// Returns `true` if `line` is an empty line which should be ignored,
+  /// because it doesn't terminate blocks.
+  fn is_ignored_blank(&self, line: &str) -> bool {
+    self.terminator != Terminator::BlankLine && line.is_empty()
+  }
+
+  /// Write the block terminator line to `buf`.
+  fn put_terminator(&self, buf: &mut BytesMut) {
+    match self.terminator {
+      Terminator::BlankLine => buf.put_u8(b'\n'),
+      Terminator::DotLine => buf.put(&b".\n"[..])
+    }
+  }
+
+  /// Returns `true` if comments are enabled and `line` is a comment line.
+  fn is_comment(&self, line: &str) -> bool {
+    self.allow_comments && line.starts_with('#')
+  }
+
+  /// Split a key/value line into its key and value, applying the value
+  /// processing options and limits.
+  fn split_line<'a>(
+    &self,
+    line: &'a str
+  ) -> Result<Option<(&'a str, &'a str)>, Error> {
+    let kv = if self.separator_any_whitespace {
+      split_kv_any_ws(line)
+    } else {
+      split_kv(line)
+    };
+    let (k, v) = match kv {
+      Some(kv) => kv,
+      None => return Ok(None)
+    };
+    let v = if self.trim_values {
+      v.trim_matches(|c: char| c.is_ascii_whitespace())
+    } else {
+      v
+    };
+    self.check_value_len(k, v)?;
+    Ok(Some((k, v)))
+  }
+
+  /// Make sure that the value `v` of the key `k` doesn't exceed the maximum
+  /// value length.
+  fn check_value_len(&self, k: &str, v: &str) -> Result<(), Error> {
+    if v.len() > self.max_value_len {
+      return Err(Error::LimitExceeded(format!(
+        "Value of key '{}' exceeds maximum length",
+        k
+      )));
+    }
+    Ok(())
+  }
+
+  /// Returns the content of `line` if continuations are enabled and `line`
+  /// is a continuation line.
+  fn continuation<'a>(&self, line: &'a str) -> Option<&'a str> {
+    if self.allow_continuations && line.starts_with([' ', '\t']) {
+      Some(line.trim_start_matches([' ', '\t']))
+    } else {
+      None
+    }
+  }
+
+  /// Append a continuation line to the value of the last key that was added
+  /// to `params`, and return the new value.
+  fn continue_value(
+    &self,
+    params: &Params,
+    cont: &str
+  ) -> Result<(String, String), Error> {
+    let key = match self.last_key {
+      Some(ref key) => key.clone(),
+      None => {
+        return Err(Error::BadFormat(
+          "Continuation line without a preceding key".to_string()
+        ))
+      }
+    };
+    let mut value = params.get_str(&key).unwrap_or_default().to_string();
+    value.push(' ');
+    value.push_str(cont);
+    self.check_value_len(&key, &value)?;
+    Ok((key, value))
+  }
+
+  /// Make sure that the integrity trailer of the current telegram has been
+  /// received and that it matches the received telegram.
+  fn verify_integrity(&mut self) -> Result<(), Error> {
+    match self.crc_trailer.take() {
+      Some(crc) if crc == self.crc.finish() => Ok(()),
+      Some(_) => {
+        Err(Error::BadFormat("Integrity check failed".to_string()))
+      }
+      None => {
+        Err(Error::BadFormat("Missing integrity trailer".to_string()))
+      }
+    }
+  }
+
+  /// Begin calculating the authentication code of a telegram, if
+  /// authentication is enabled.
+  #[cfg(feature = "hmac")]
+  fn start_hmac(&mut self, topic_line: &str) {
+    self.hmac_trailer = None;
+    self.hmac = self.hmac_key.clone().map(|mut mac| {
+      mac.update(topic_line.as_bytes());
+      mac.update(b"\n");
+      mac
+    });
+  }
+
+  /// Feed a telegram line into the authentication code.  Returns `true` if
+  /// the line was the authentication trailer.
+  #[cfg(feature = "hmac")]
+  fn update_hmac(&mut self, line: &str) -> Result<bool, Error> {
+    let mac = match self.hmac.as_mut() {
+      Some(mac) => mac,
+      None => return Ok(false)
+    };
+    if self.hmac_trailer.is_some() {
+      return Err(Error::BadFormat(
+        "Unexpected data after authentication trailer".to_string()
+      ));
+    }
+    if let Some(hex) = line.strip_prefix(HMAC_KEY) {
+      if let Some(hex) = hex.strip_prefix(' ') {
+        let tag = from_hex(hex).ok_or_else(|| {
+          Error::BadFormat("Invalid authentication trailer".to_string())
+        })?;
+        self.hmac_trailer = Some(tag);
+        return Ok(true);
+      }
+    }
+    mac.update(line.as_bytes());
+    mac.update(b"\n");
+    Ok(false)
+  }
+
+  /// Make sure that the authentication trailer of the current telegram has
+  /// been received and that it matches the received telegram.
+  #[cfg(feature = "hmac")]
+  fn verify_hmac(&mut self) -> Result<(), Error> {
+    let mac = match self.hmac.take() {
+      Some(mac) => mac,
+      None => return Ok(())
+    };
+    match self.hmac_trailer.take() {
+      Some(tag) => mac.verify_slice(&tag).map_err(|_| {
+        Error::BadFormat("Authentication check failed".to_string())
+      }),
+      None => Err(Error::BadFormat(
+        "Missing authentication trailer".to_string()
+      ))
+    }
+  }
+
   /*
   fn getline_owned(
     &mut self,
     buf: &mut BytesMut
   ) -> Result<Option<String>, Error> {
-    let (read_to, newline_offset) = self.find_newline(&buf);
+    let (read_to, newline_offset) = self.find_newline(buf);
     match newline_offset {
       Some(offset) => {
         // Found an eol
@@ -242,8 +1323,12 @@ impl Codec {
   */
 
   /// Get index of the next end of line in `buf`.
+  ///
+  /// Only complete lines are handed to the callers for utf-8 validation, so
+  /// a multi-byte character which straddles two reads is never validated
+  /// partially.
   fn get_eol_idx(&mut self, buf: &BytesMut) -> Result<Option<usize>, Error> {
-    let (read_to, newline_offset) = self.find_newline(&buf);
+    let (read_to, newline_offset) = self.find_newline(buf);
     match newline_offset {
       Some(offset) => {
         // Found an eol
@@ -279,19 +1364,23 @@ impl Codec {
     buf: &mut BytesMut
   ) -> Result<Option<Telegram>, Error> {
     loop {
-      if let Some(idx) = self.get_eol_idx(buf)? {
+      let idx = self.get_eol_idx(buf).map_err(|e| self.begin_resync(e))?;
+      if let Some(idx) = idx {
         let line = buf.split_to(idx);
         let line = &line[..line.len() - 1];
-        let line = utf8(without_carriage_return(line))?;
+        let line = utf8(without_carriage_return(line))
+          .map_err(|e| self.begin_resync(e.into()))?;
 
-        // Empty line marks end of Telegram
-        if line.is_empty() {
-          // mem::take() can replace a member of a struct.
-          // (This requires Default to be implemented for the object being
-          // taken).
-          return Ok(Some(mem::take(&mut self.tg)));
-        } else {
-          self.decode_telegram_line(&line)?;
+        // Terminator line marks end of Telegram
+        if self.is_terminator(line.as_bytes()) {
+          if self.skip_leading_blanks && self.tg.get_topic().is_none() {
+            continue;
+          }
+          return self.finish_telegram().map(Some);
+        } else if !self.is_ignored_blank(line) {
+          self
+            .decode_telegram_line(line)
+            .map_err(|e| self.begin_resync(e))?;
         }
       } else {
         // Returning Ok(None) instructs the FramedRead that more data is
@@ -302,6 +1391,204 @@ impl Codec {
   }
 
 
+  /// (New) data is available in the input buffer, and telegrams are length
+  /// prefixed.
+  ///
+  /// Wait for the length prefix and the entire telegram block to arrive, and
+  /// then parse the block's lines and return the telegram.
+  fn decode_telegram_frame(
+    &mut self,
+    buf: &mut BytesMut
+  ) -> Result<Option<Telegram>, Error> {
+    if buf.len() < 4 {
+      // Need more data
+      return Ok(None);
+    }
+    let len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+    if len > self.max_frame_len {
+      return Err(Error::LimitExceeded(format!(
+        "Telegram length {} exceeds maximum of {}",
+        len, self.max_frame_len
+      )));
+    }
+    if buf.len() - 4 < len {
+      // Need more data
+      buf.reserve(len - (buf.len() - 4));
+      return Ok(None);
+    }
+
+    buf.advance(4);
+    let block = buf.split_to(len);
+
+    // The entire frame has been consumed, so the stream remains aligned even
+    // if the telegram is malformed.  Make sure no partial telegram is left
+    // behind for the next frame.
+    match self.decode_telegram_block(&block) {
+      Ok(tg) => Ok(Some(tg)),
+      Err(e) => {
+        self.discard_telegram();
+        Err(e)
+      }
+    }
+  }
+
+  /// Parse the lines of a length-prefixed telegram block.
+  fn decode_telegram_block(
+    &mut self,
+    block: &[u8]
+  ) -> Result<Telegram, Error> {
+    let mut rest = block;
+    while !rest.is_empty() {
+      let line = match rest.iter().position(|b| *b == b'\n') {
+        Some(idx) => {
+          let line = &rest[..idx];
+          rest = &rest[idx + 1..];
+          line
+        }
+        None => mem::take(&mut rest)
+      };
+      let line = utf8(without_carriage_return(line))?;
+
+      if self.is_ignored_blank(line) {
+        continue;
+      }
+
+      // Terminator line marks end of Telegram
+      if self.is_terminator(line.as_bytes()) {
+        if self.skip_leading_blanks && self.tg.get_topic().is_none() {
+          continue;
+        }
+        if !rest.is_empty() {
+          return Err(Error::BadFormat(
+            "Unexpected data after end of telegram".to_string()
+          ));
+        }
+        break;
+      }
+      self.decode_telegram_line(line)?;
+    }
+
+    self.finish_telegram()
+  }
+
+  /// Throw away a partially received telegram, and any state associated
+  /// with it.
+  fn discard_telegram(&mut self) {
+    self.tg.clear();
+    self.last_key = None;
+    self.crc_trailer = None;
+    #[cfg(feature = "hmac")]
+    {
+      self.hmac = None;
+      self.hmac_trailer = None;
+    }
+  }
+
+
+  /// Check for, and discard, a byte order mark at the start of the stream.
+  ///
+  /// Returns `false` if more data is needed to determine whether the stream
+  /// starts with a byte order mark.
+  fn check_bom(&mut self, buf: &mut BytesMut) -> bool {
+    let line_state = match self.state {
+      CodecState::Telegram => self.framing == Framing::Lines,
+      CodecState::Params
+      | CodecState::ParamsCount
+      | CodecState::KVLines
+      | CodecState::TopicKVLines
+      | CodecState::Greeting => true,
+      _ => false
+    };
+    if !self.strip_bom || !line_state {
+      self.bom_checked = true;
+      return true;
+    }
+
+    let n = cmp::min(buf.len(), BOM.len());
+    if buf[..n] != BOM[..n] {
+      self.bom_checked = true;
+      return true;
+    }
+    if n < BOM.len() {
+      // Could be the start of a byte order mark
+      return false;
+    }
+
+    buf.advance(BOM.len());
+    self.bom_checked = true;
+    true
+  }
+
+  /// Make sure the first byte following a completed binary transfer can
+  /// begin a telegram topic.
+  fn check_binary_alignment(&mut self, buf: &BytesMut) -> Result<(), Error> {
+    if self.state != CodecState::Telegram {
+      // The application expects something other than a telegram.
+      self.check_alignment = false;
+      return Ok(());
+    }
+    if let Some(&b) = buf.first() {
+      self.check_alignment = false;
+      if b.is_ascii() && !b.is_ascii_alphabetic() {
+        return Err(Error::BadFormat(
+          "Data following binary transfer is not a telegram".to_string()
+        ));
+      }
+    }
+    Ok(())
+  }
+
+  /// An error occurred in the middle of a telegram.  If resynchronization
+  /// is enabled, make sure the rest of the telegram will be skipped.
+  fn begin_resync(&mut self, e: Error) -> Error {
+    if self.resync_on_error {
+      self.resyncing = true;
+      self.resync_line_empty = true;
+      self.next_line_index = 0;
+    }
+    e
+  }
+
+  /// Discard input up to and including the next empty line.  Returns `true`
+  /// once the empty line has been found.
+  fn resync(&mut self, buf: &mut BytesMut) -> bool {
+    while let Some(idx) = buf.iter().position(|b| *b == b'\n') {
+      let line = buf.split_to(idx + 1);
+      let term = self.resync_line_empty
+        && self.is_terminator(without_carriage_return(&line[..idx]));
+      self.resync_line_empty = true;
+      if term {
+        self.resyncing = false;
+        return true;
+      }
+    }
+
+    // No newline found; discard the partial line, unless it may turn out to
+    // be a terminator line.
+    let partial = without_carriage_return(&buf[..]);
+    if !(self.resync_line_empty && self.is_terminator(partial)) {
+      self.resync_line_empty = self.resync_line_empty && buf.is_empty();
+      buf.clear();
+    }
+    false
+  }
+
+  /// A complete telegram has been received.  Perform final checks and return
+  /// it.
+  fn finish_telegram(&mut self) -> Result<Telegram, Error> {
+    if self.integrity {
+      self.verify_integrity()?;
+    }
+    #[cfg(feature = "hmac")]
+    self.verify_hmac()?;
+    self.last_key = None;
+
+    // mem::take() can replace a member of a struct.
+    // (This requires Default to be implemented for the object being taken).
+    Ok(mem::take(&mut self.tg))
+  }
+
+
   /// Read buffer line-by-line, split each line at the first space character
   /// and store the left part as a key and the right part as a value in a
   /// Params structure.
@@ -316,22 +1603,29 @@ impl Codec {
         let line = &line[..line.len() - 1];
         let line = utf8(without_carriage_return(line))?;
 
-        // Empty line marks end of Params
-        if line.is_empty() {
+        // Terminator line marks end of Params
+        if self.is_terminator(line.as_bytes()) {
           // Revert to expecting a telegram once a Params has been completed.
           // The application can override this when needed.
           self.state = CodecState::Telegram;
+          self.last_key = None;
 
           // mem::take() can replace a member of a struct.
           // (This requires Default to be implemented for the object being
           // taken).
           return Ok(Some(mem::take(&mut self.params)));
-        } else {
-          let idx = line.find(' ');
-          if let Some(idx) = idx {
-            let (k, v) = line.split_at(idx);
-            let v = &v[1..v.len()];
+        } else if let Some(cont) = self.continuation(line) {
+          let (k, v) = self.continue_value(&self.params, cont)?;
+          self.params.add_param(k, v)?;
+        } else if !self.is_comment(line) {
+          if let Some((k, v)) = self.split_line(line)? {
+            self.check_duplicate(&self.params, k)?;
+            self.check_num_params(&self.params, k)?;
+            let v = self.join_repeated(&self.params, k, v)?;
             self.params.add_param(k, v)?;
+            if self.allow_continuations {
+              self.last_key = Some(k.to_string());
+            }
           }
         }
       } else {
@@ -341,6 +1635,46 @@ impl Codec {
     }
   }
 
+  /// Read a fixed number of key/value lines and store them in a Params
+  /// structure.
+  fn decode_params_count(
+    &mut self,
+    buf: &mut BytesMut
+  ) -> Result<Option<Params>, Error> {
+    while self.params_remain > 0 {
+      let idx = match self.get_eol_idx(buf)? {
+        Some(idx) => idx,
+        None => {
+          // Need more data
+          return Ok(None);
+        }
+      };
+      let line = buf.split_to(idx);
+      let line = &line[..line.len() - 1];
+      let line = utf8(without_carriage_return(line))?;
+
+      if self.is_comment(line) {
+        continue;
+      }
+      let (k, v) = match self.split_line(line)? {
+        Some(kv) => kv,
+        None => {
+          return Err(Error::BadFormat("Expected a key/value line".to_string()))
+        }
+      };
+      self.check_duplicate(&self.params, k)?;
+      self.check_num_params(&self.params, k)?;
+      let v = self.join_repeated(&self.params, k, v)?;
+      self.params.add_param(k, v)?;
+      self.params_remain -= 1;
+    }
+
+    // Revert to expecting a telegram once all the lines have been received.
+    self.state = CodecState::Telegram;
+
+    Ok(Some(mem::take(&mut self.params)))
+  }
+
   /// Rea buffer line-by-line, split each at the first space character and
   /// store the left and right part in a vector.  When an empty line is
   /// encountered, return the vector and return to expecting a [`Telegram`].
@@ -355,8 +1689,8 @@ impl Codec {
         let line = &line[..line.len() - 1];
         let line = utf8(without_carriage_return(line))?;
 
-        // Empty line marks end of Params
-        if line.is_empty() {
+        // Terminator line marks end of Params
+        if self.is_terminator(line.as_bytes()) {
           // Revert to expecting a telegram once a KVLines  has been
           // completed.
           // The application can override this when needed.
@@ -366,11 +1700,32 @@ impl Codec {
           // (This requires Default to be implemented for the object being
           // taken).
           return Ok(Some(mem::take(&mut self.kvlines)));
-        } else {
-          let idx = line.find(' ');
-          if let Some(idx) = idx {
-            let (k, v) = line.split_at(idx);
-            let v = &v[1..v.len()];
+        } else if let Some(cont) = self.continuation(line) {
+          let max_value_len = self.max_value_len;
+          let (key, value) = match self.kvlines.last_mut() {
+            Some(kv) => kv,
+            None => {
+              return Err(Error::BadFormat(
+                "Continuation line without a preceding key".to_string()
+              ))
+            }
+          };
+          value.push(' ');
+          value.push_str(cont);
+          if value.len() > max_value_len {
+            return Err(Error::LimitExceeded(format!(
+              "Value of key '{}' exceeds maximum length",
+              key
+            )));
+          }
+        } else if !self.is_comment(line) {
+          if let Some((k, v)) = self.split_line(line)? {
+            if self.kvlines.len() >= self.max_params {
+              return Err(Error::LimitExceeded(format!(
+                "More than {} parameters",
+                self.max_params
+              )));
+            }
             self.kvlines.append(k, v);
           }
         }
@@ -381,6 +1736,44 @@ impl Codec {
     }
   }
 
+  /// Read a topic line, and then key/value lines using
+  /// [`decode_kvlines()`](Self::decode_kvlines) until the terminator line is
+  /// encountered.
+  fn decode_topic_kvlines(
+    &mut self,
+    buf: &mut BytesMut
+  ) -> Result<Option<(String, KVLines)>, Error> {
+    while self.kvlines_topic.is_none() {
+      let idx = match self.get_eol_idx(buf)? {
+        Some(idx) => idx,
+        None => {
+          // Need more data
+          return Ok(None);
+        }
+      };
+      let line = buf.split_to(idx);
+      let line = &line[..line.len() - 1];
+      let line = utf8(without_carriage_return(line))?;
+
+      if self.is_terminator(line.as_bytes()) {
+        if self.skip_leading_blanks {
+          continue;
+        }
+        return Err(Error::BadFormat("Missing topic".to_string()));
+      } else if !self.is_ignored_blank(line) && !self.is_comment(line) {
+        validate_topic(line)?;
+        self.kvlines_topic = Some(line.to_string());
+      }
+    }
+
+    let kvlines = match self.decode_kvlines(buf)? {
+      Some(kvlines) => kvlines,
+      None => return Ok(None)
+    };
+    let topic = self.kvlines_topic.take().unwrap_or_default();
+    Ok(Some((topic, kvlines)))
+  }
+
 
   /// Set the decoder to treat the next `size` bytes as raw bytes to be
   /// received in chunks as BytesMut.
@@ -398,10 +1791,27 @@ impl Codec {
   pub fn expect_chunks(&mut self, size: usize) {
     //println!("Expecting bin {}", size);
     self.state = CodecState::Chunks;
-    self.bin_remain = size;
+    self.begin_transfer(size);
+  }
+
+  /// Set the decoder to treat all remaining data on the stream as raw bytes,
+  /// which are returned in chunks as they arrive.
+  ///
+  /// # Decoder behavior
+  /// The decoder will return an [`Input::RawChunk(buf)`](Input::RawChunk) to
+  /// the application each time a new chunk has been received.  Once the
+  /// underlying stream has been closed, and all buffered data has been
+  /// returned, the stream ends.
+  ///
+  /// The decoder will then revert to expect an [`Input::Telegram`], which is
+  /// only relevant if the underlying reader can be resumed after having
+  /// reported end-of-stream.
+  pub fn expect_chunks_until_eof(&mut self) {
+    self.state = CodecState::RawChunks;
   }
 
 
+
   /// Expect a immutable buffer of a certain size to be received.
   ///
   /// The returned buffer will be stored in process memory.
@@ -418,7 +1828,35 @@ impl Codec {
       return Err(Error::InvalidSize("The size must not be zero".to_string()));
     }
     self.state = CodecState::Bytes;
-    self.bin_remain = size;
+    self.begin_transfer(size);
+    self.buf = BytesMut::with_capacity(size);
+    Ok(())
+  }
+
+
+  /// Expect a JSON object of a certain size to be received.
+  ///
+  /// The object must be flat; each of its members is stored as a parameter
+  /// in a [`Params`] buffer:
+  /// - String values are stored as-is.
+  /// - Numbers and booleans are stored in their JSON text form.
+  /// - `null`, arrays and objects are rejected.
+  ///
+  /// # Decoder behavior
+  /// Once a complete buffer has been successfully received the `Decoder` will
+  /// return an [`Input::Params(params)`](Input::Params).  If the buffer is
+  /// not a valid JSON object, or contains unsupported values or invalid
+  /// keys, an `Error::BadFormat` will be returned.
+  ///
+  /// Once the entire buffer has been received by the `Decoder` it will revert
+  /// to expect an [`Input::Telegram`].
+  #[cfg(feature = "json")]
+  pub fn expect_json(&mut self, size: usize) -> Result<(), Error> {
+    if size == 0 {
+      return Err(Error::InvalidSize("The size must not be zero".to_string()));
+    }
+    self.state = CodecState::Json;
+    self.begin_transfer(size);
     self.buf = BytesMut::with_capacity(size);
     Ok(())
   }
@@ -440,7 +1878,30 @@ impl Codec {
       return Err(Error::InvalidSize("The size must not be zero".to_string()));
     }
     self.state = CodecState::BytesMut;
-    self.bin_remain = size;
+    self.begin_transfer(size);
+    self.buf = BytesMut::with_capacity(size);
+    Ok(())
+  }
+
+
+  /// Expect a buffer of a certain size to be received, and returned as a
+  /// `Vec<u8>`.
+  ///
+  /// The returned buffer will be stored in process memory.
+  ///
+  /// # Decoder behavior
+  /// Once a complete buffer has been successfully received the `Decoder` will
+  /// return an [`Input::Vec(v)`](Input::Vec) where `v` is a `Vec<u8>`
+  /// containing the entire buffer.
+  ///
+  /// Once the entire buffer has been received by the `Decoder` it will revert
+  /// to expect an [`Input::Telegram`].
+  pub fn expect_vec(&mut self, size: usize) -> Result<(), Error> {
+    if size == 0 {
+      return Err(Error::InvalidSize("The size must not be zero".to_string()));
+    }
+    self.state = CodecState::Vec;
+    self.begin_transfer(size);
     self.buf = BytesMut::with_capacity(size);
     Ok(())
   }
@@ -457,6 +1918,10 @@ impl Codec {
   ///
   /// Once the entire buffer has been received by the `Decoder` it will revert
   /// to expect an [`Input::Telegram`].
+  ///
+  /// Returns `Error::InvalidSize` if `size` exceeds the maximum file size set
+  /// using [`Codec::set_max_file_size()`], in which case the file is not
+  /// created.
   pub fn expect_file<P: Into<PathBuf>>(
     &mut self,
     pathname: P,
@@ -465,12 +1930,18 @@ impl Codec {
     if size == 0 {
       return Err(Error::InvalidSize("The size must not be zero".to_string()));
     }
+    if size > self.max_file_size {
+      return Err(Error::InvalidSize(format!(
+        "The size exceeds the maximum file size of {} bytes",
+        self.max_file_size
+      )));
+    }
     self.state = CodecState::File;
     let pathname = pathname.into();
     self.writer = Some(Box::new(File::create(&pathname)?));
     self.pathname = Some(pathname);
 
-    self.bin_remain = size;
+    self.begin_transfer(size);
 
     Ok(())
   }
@@ -498,10 +1969,101 @@ impl Codec {
     }
     self.state = CodecState::Writer;
     self.writer = Some(Box::new(writer));
-    self.bin_remain = size;
+    self.begin_transfer(size);
     Ok(())
   }
 
+  /// Expect a buffer of a certain size to be received, which should both be
+  /// written to a supplied writer and returned to the application.
+  ///
+  /// The writer's ownership will be transferred to the `Decoder` and will
+  /// automatically be dropped once the entire buffer has been written.  The
+  /// returned buffer will be stored in process memory.
+  ///
+  /// # Decoder behavior
+  /// Each chunk is written to the writer as it arrives.  Once a complete
+  /// buffer has been successfully received the `Decoder` will return an
+  /// [`Input::Bytes(b)`](Input::Bytes) where `b` is a [`bytes::Bytes`]
+  /// containing the entire buffer.
+  ///
+  /// Once the entire buffer has been received by the `Decoder` it will revert
+  /// to expect an [`Input::Telegram`].
+  pub fn expect_tee<W: 'static + Write + Send + Sync>(
+    &mut self,
+    writer: W,
+    size: usize
+  ) -> Result<(), Error> {
+    if size == 0 {
+      return Err(Error::InvalidSize("The size must not be zero".to_string()));
+    }
+    self.state = CodecState::Tee;
+    self.writer = Some(Box::new(writer));
+    self.begin_transfer(size);
+    self.buf = BytesMut::with_capacity(size);
+    Ok(())
+  }
+
+  /// Expect a certain amount of bytes of data to arrive from the peer, and
+  /// pass each chunk to the callback `f` as it arrives.
+  ///
+  /// This can be used to process large buffers incrementally, such as
+  /// feeding them to a hasher, without storing them.  The callback's
+  /// ownership will be transferred to the `Decoder` and will automatically be
+  /// dropped once the entire buffer has been received.
+  ///
+  /// # Decoder behavior
+  /// On successful completion the Decoder will return an
+  /// [`Input::SinkDone`] to signal that the entire buffer has been received
+  /// and passed to the callback.
+  ///
+  /// Once the entire buffer has been received by the `Decoder` it will revert
+  /// to expect an [`Input::Telegram`].
+  pub fn expect_sink<F>(&mut self, f: F, size: usize) -> Result<(), Error>
+  where
+    F: 'static + FnMut(&[u8]) + Send
+  {
+    if size == 0 {
+      return Err(Error::InvalidSize("The size must not be zero".to_string()));
+    }
+    self.state = CodecState::Sink;
+    self.sink = Some(Mutex::new(Box::new(f)));
+    self.begin_transfer(size);
+    Ok(())
+  }
+
+  /// Tell the Decoder to expect a [`Telegram`], abandoning whatever it was
+  /// currently expecting.
+  ///
+  /// Any partially received telegram, buffer or binary transfer is
+  /// discarded, and an open file or writer is closed.  This can be used to
+  /// get the decoder back into a known state, for instance after a protocol
+  /// error has been detected by the application.
+  ///
+  /// # Decoder behavior
+  /// The decoder will next return an [`Input::Telegram(tg)`](Input::Telegram)
+  /// once a complete `Telegram` has been received.
+  pub fn expect_telegram(&mut self) {
+    self.state = CodecState::Telegram;
+    self.tg.clear();
+    self.params.clear();
+    self.kvlines.clear();
+    self.kvlines_topic = None;
+    self.last_key = None;
+    self.crc_trailer = None;
+    #[cfg(feature = "hmac")]
+    {
+      self.hmac = None;
+      self.hmac_trailer = None;
+    }
+    self.params_remain = 0;
+    self.writer = None;
+    self.sink = None;
+    self.pathname = None;
+    self.buf = BytesMut::new();
+    self.bin_remain = 0;
+    self.update_transfer();
+  }
+
   /// Tell the Decoder to expect lines of key/value pairs.
   ///
   /// # Decoder behavior
@@ -513,19 +2075,55 @@ impl Codec {
   /// to expect an [`Input::Telegram`].
   pub fn expect_params(&mut self) {
     self.state = CodecState::Params;
+    self.last_key = None;
+  }
+
+  /// Tell the Decoder to expect exactly `n` lines of key/value pairs, without
+  /// a terminating line.
+  ///
+  /// Each received line, except comment lines, counts towards `n`.  Lines
+  /// which are not key/value lines cause an `Error::BadFormat`.
+  ///
+  /// # Decoder behavior
+  /// On successful completion the decoder will next return an
+  /// [`Input::Params(params)`](Input::Params) once `n` key/value lines have
+  /// been received.  If `n` is zero an empty `Params` buffer is returned
+  /// immediately.
+  ///
+  /// Once the entire buffer has been received by the `Decoder` it will revert
+  /// to expect an [`Input::Telegram`].
+  pub fn expect_params_count(&mut self, n: usize) {
+    self.state = CodecState::ParamsCount;
+    self.params_remain = n;
+  }
+
+  /// Tell the Decoder to expect lines ordered key/value pairs.
+  ///
+  /// # Decoder behavior
+  /// On successful completion the Framed StreamExt next() will return an
+  /// [`Input::KVLines(kvlines)`](Input::KVLines) once a complete `KVLines`
+  /// buffer has been received.
+  ///
+  /// Once the entire buffer has been received by the `Decoder` it will revert
+  /// to expect an [`Input::Telegram`].
+  pub fn expect_kvlines(&mut self) {
+    self.state = CodecState::KVLines;
   }
 
-  /// Tell the Decoder to expect lines ordered key/value pairs.
+  /// Tell the Decoder to expect a topic line followed by lines of ordered
+  /// key/value pairs, such as those written by the
+  /// `Encoder<(&str, &KVLines)>` implementation.
   ///
   /// # Decoder behavior
-  /// On successful completion the Framed StreamExt next() will return an
-  /// [`Input::KVLines(kvlines)`](Input::KVLines) once a complete `KVLines`
-  /// buffer has been received.
+  /// On successful completion the decoder will return an
+  /// [`Input::TopicKVLines(topic, kvlines)`](Input::TopicKVLines) once the
+  /// topic and a complete `KVLines` buffer have been received.
   ///
   /// Once the entire buffer has been received by the `Decoder` it will revert
   /// to expect an [`Input::Telegram`].
-  pub fn expect_kvlines(&mut self) {
-    self.state = CodecState::KVLines;
+  pub fn expect_topic_kvlines(&mut self) {
+    self.state = CodecState::TopicKVLines;
+    self.kvlines_topic = None;
   }
 
   /// Skip a requested number of bytes.
@@ -542,9 +2140,119 @@ impl Codec {
       return Err(Error::InvalidSize("The size must not be zero".to_string()));
     }
     self.state = CodecState::Skip;
-    self.bin_remain = size;
+    self.begin_transfer(size);
+    Ok(())
+  }
+
+  /// Skip the next line, up to and including its terminating newline.
+  ///
+  /// # Decoder behavior
+  /// On successful completion the decoder will have ignored the line,
+  /// reverts back to waiting for a [`Input::Telegram`] and returns
+  /// [`Input::SkipLineDone`].
+  ///
+  /// The maximum line length applies to the skipped line; if it is exceeded
+  /// before a newline has been found the decoder returns an
+  /// `Error::BadFormat`.
+  pub fn expect_skip_line(&mut self) {
+    self.state = CodecState::SkipLine;
+  }
+
+  /// Expect a single greeting line, such as a protocol version banner, which
+  /// is not a telegram.
+  ///
+  /// # Decoder behavior
+  /// On successful completion the decoder returns an
+  /// [`Input::Greeting(line)`](Input::Greeting), where `line` is the received
+  /// line without its line ending, and reverts back to waiting for a
+  /// [`Input::Telegram`].
+  ///
+  /// The maximum line length applies to the greeting line.  A line which is
+  /// not valid utf-8 causes an error.
+  pub fn expect_greeting(&mut self) {
+    self.state = CodecState::Greeting;
+  }
+
+
+  /// Encode a sequence of [`Telegram`]s into `buf`.
+  ///
+  /// The space required by all the telegrams is reserved in `buf` up front,
+  /// after which the telegrams are encoded in order, just as if each of them
+  /// had been passed to `encode()`.
+  ///
+  /// ```
+  /// use bytes::BytesMut;
+  /// use blather::{Codec, Telegram};
+  ///
+  /// fn main() {
+  ///   let mut codec = Codec::new();
+  ///   let tgs = vec![
+  ///     Telegram::new_topic("One").unwrap(),
+  ///     Telegram::new_topic("Two").unwrap()
+  ///   ];
+  ///   let mut buf = BytesMut::new();
+  ///   codec.encode_batch(&tgs, &mut buf).unwrap();
+  ///   assert_eq!(&buf[..], b"One\n\nTwo\n\n");
+  /// }
+  /// ```
+  pub fn encode_batch<'a, I: IntoIterator<Item = &'a Telegram>>(
+    &mut self,
+    tgs: I,
+    buf: &mut BytesMut
+  ) -> Result<(), Error> {
+    let tgs: Vec<&Telegram> = tgs.into_iter().collect();
+
+    let size = tgs.iter().map(|tg| tg.calc_buf_size()).sum();
+    buf.reserve(size);
+
+    for tg in tgs {
+      self.encode(tg, buf)?;
+    }
     Ok(())
   }
+
+
+  /// Encode a [`Telegram`] immediately followed by a binary payload.
+  ///
+  /// The length of `payload` is stored in the parameter `len_key` of a copy
+  /// of `tg`, which guarantees that the announced length matches the number
+  /// of bytes that follow the telegram.  The receiver is expected to decode
+  /// the telegram and then call [`Codec::expect_bytes()`] (or one of the
+  /// other binary `expect_*` methods) using the announced length.
+  ///
+  /// Since the binary `expect_*` methods do not accept a length of zero, an
+  /// empty `payload` is rejected with `Error::BadFormat`, and nothing is
+  /// written to `buf`.
+  ///
+  /// ```
+  /// use bytes::BytesMut;
+  /// use blather::{Codec, Telegram};
+  ///
+  /// fn main() {
+  ///   let mut codec = Codec::new();
+  ///   let tg = Telegram::new_topic("Data").unwrap();
+  ///   let mut buf = BytesMut::new();
+  ///   codec.encode_with_payload(&tg, b"1234", "Len", &mut buf).unwrap();
+  ///   assert_eq!(&buf[..], b"Data\nLen 4\n\n1234");
+  /// }
+  /// ```
+  pub fn encode_with_payload(
+    &mut self,
+    tg: &Telegram,
+    payload: &[u8],
+    len_key: &str,
+    buf: &mut BytesMut
+  ) -> Result<(), Error> {
+    if payload.is_empty() {
+      return Err(Error::BadFormat(
+        "The payload must not be empty".to_string()
+      ));
+    }
+    let mut tg = tg.clone();
+    tg.add_param(len_key, payload.len())?;
+    self.encode(&tg, buf)?;
+    self.encode(payload, buf)
+  }
 }
 
 fn utf8(buf: &[u8]) -> Result<&str, io::Error> {
@@ -556,6 +2264,77 @@ fn utf8(buf: &[u8]) -> Result<&str, io::Error> {
   })
 }
 
+/// Parse a flat JSON object into a `Params`.
+#[cfg(feature = "json")]
+fn json_to_params(buf: &[u8]) -> Result<Params, Error> {
+  use serde_json::Value;
+
+  let obj = match serde_json::from_slice(buf) {
+    Ok(Value::Object(obj)) => obj,
+    Ok(_) => {
+      return Err(Error::BadFormat("JSON value is not an object".to_string()))
+    }
+    Err(e) => return Err(Error::BadFormat(format!("Invalid JSON; {}", e)))
+  };
+
+  let mut params = Params::new();
+  for (k, v) in obj {
+    let v = match v {
+      Value::String(s) => s,
+      Value::Number(n) => n.to_string(),
+      Value::Bool(b) => b.to_string(),
+      _ => {
+        return Err(Error::BadFormat(format!(
+          "Unsupported JSON value for key '{}'",
+          k
+        )))
+      }
+    };
+    params.add_param(k, v)?;
+  }
+  Ok(params)
+}
+
+/// Emit a tracing event describing the outcome of a decode call.
+#[cfg(feature = "tracing")]
+fn trace_frame(frame: &Result<Option<Input>, Error>) {
+  match frame {
+    Ok(Some(Input::Telegram(tg))) => tracing::debug!(
+      topic = tg.get_topic().unwrap_or_default(),
+      params = tg.num_params(),
+      "Telegram received"
+    ),
+    Ok(Some(Input::Params(params))) => {
+      tracing::debug!(params = params.len(), "Params received")
+    }
+    Ok(Some(Input::KVLines(kvlines))) => {
+      tracing::debug!(lines = kvlines.len(), "KVLines received")
+    }
+    Ok(Some(Input::TopicKVLines(topic, kvlines))) => tracing::debug!(
+      topic = topic.as_str(),
+      lines = kvlines.len(),
+      "Topic KVLines received"
+    ),
+    Ok(Some(Input::DecodeError(e))) => {
+      tracing::warn!(error = %e, "Malformed telegram discarded")
+    }
+    Ok(_) => {}
+    Err(e) => tracing::warn!(error = %e, "Decode failed")
+  }
+}
+
+/// Decode a hex string, such as the value of an authentication trailer.
+#[cfg(feature = "hmac")]
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+  if s.len() % 2 != 0 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+    return None;
+  }
+  (0..s.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+    .collect()
+}
+
 fn without_carriage_return(s: &[u8]) -> &[u8] {
   if let Some(&b'\r') = s.last() {
     &s[..s.len() - 1]
@@ -565,26 +2344,96 @@ fn without_carriage_return(s: &[u8]) -> &[u8] {
 }
 
 
-/// A Decoder implementation that is used to assist in decoding data arriving
-/// over a DDM client interface.
-///
-/// The default behavior for the Decoder is to wait for a Telegram buffer.  It
-/// will, on success, return an `Input::Telegram(tg)`, where `tg` is a
-/// `blather::Telegram` object.
-impl Decoder for Codec {
-  type Item = Input;
-  type Error = crate::err::Error;
+impl Codec {
+  /// Decode the next frame from the input buffer, and update the transfer
+  /// information and statistics.
+  fn decode_frame(
+    &mut self,
+    buf: &mut BytesMut
+  ) -> Result<Option<Input>, Error> {
+    let binary = !matches!(
+      self.state,
+      CodecState::Telegram
+        | CodecState::Params
+        | CodecState::ParamsCount
+        | CodecState::KVLines
+        | CodecState::TopicKVLines
+        | CodecState::SkipLine
+        | CodecState::Greeting
+    );
+    let len = buf.len();
+
+    let frame = self.decode_input(buf);
+    self.update_transfer();
+    #[cfg(feature = "tracing")]
+    trace_frame(&frame);
+
+    if binary {
+      self.stats.binary_bytes += (len - buf.len()) as u64;
+    }
+    match frame {
+      Ok(Some(Input::Telegram(_))) => self.stats.telegrams += 1,
+      Ok(Some(Input::Params(_))) => self.stats.params += 1,
+      Ok(Some(Input::KVLines(_))) | Ok(Some(Input::TopicKVLines(..))) => {
+        self.stats.kvlines += 1
+      }
+      Ok(Some(Input::DecodeError(_))) | Err(_) => self.stats.errors += 1,
+      Ok(_) => {}
+    }
+
+    frame
+  }
+
+  /// Decode the next frame from the input buffer, based on the current
+  /// decoder state.
+  fn decode_input(
+    &mut self,
+    buf: &mut BytesMut
+  ) -> Result<Option<Input>, Error> {
+    if !self.bom_checked && !buf.is_empty() && !self.check_bom(buf) {
+      // Need more data
+      return Ok(None);
+    }
+    if self.check_alignment {
+      self.check_binary_alignment(buf)?;
+    }
 
-  fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Input>, Error> {
     // The codec's internal decoder state denotes whether lines or binary data
     // is currently being expected.
     match self.state {
       CodecState::Telegram => {
+        if self.resyncing && !self.resync(buf) {
+          // Need more data
+          return Ok(None);
+        }
+
         // If decode_telegram_lines returns Some(value) it means that a
         // complete buffer has been received.
-        let tg = self.decode_telegram_lines(buf)?;
+        let tg = match self.framing {
+          Framing::Lines => self.decode_telegram_lines(buf),
+          Framing::LengthPrefixed => self.decode_telegram_frame(buf)
+        };
+        let tg = match tg {
+          Ok(tg) => tg,
+          Err(e @ Error::BadFormat(_)) if self.resync_on_error => {
+            // Discard the partially received telegram.
+            self.discard_telegram();
+            return Ok(Some(Input::DecodeError(e)));
+          }
+          Err(e @ Error::IO(_)) if self.resyncing => {
+            // A line which is not valid UTF-8; the decoder has begun
+            // resynchronizing.
+            self.discard_telegram();
+            return Ok(Some(Input::DecodeError(e)));
+          }
+          Err(e) => return Err(e)
+        };
         if let Some(tg) = tg {
           // A complete Telegram was received
+          if let Some(ref mut f) = self.on_telegram {
+            let f = f.get_mut().unwrap_or_else(PoisonError::into_inner);
+            f(&tg);
+          }
           return Ok(Some(Input::Telegram(tg)));
         }
 
@@ -603,6 +2452,10 @@ impl Decoder for Codec {
         // Returning Ok(None) tells the caller that we need more data
         Ok(None)
       }
+      CodecState::ParamsCount => {
+        let params = self.decode_params_count(buf)?;
+        Ok(params.map(Input::Params))
+      }
       CodecState::KVLines => {
         // If decode_telegram_lines returns Some(value) it means that a
         // complete buffer has been received.
@@ -615,6 +2468,10 @@ impl Decoder for Codec {
         // Returning Ok(None) tells the caller that we need more data
         Ok(None)
       }
+      CodecState::TopicKVLines => {
+        let res = self.decode_topic_kvlines(buf)?;
+        Ok(res.map(|(topic, kvlines)| Input::TopicKVLines(topic, kvlines)))
+      }
       CodecState::Chunks => {
         if buf.is_empty() {
           // Need more data
@@ -635,6 +2492,15 @@ impl Decoder for Codec {
         // if it has received all the expected binary data.
         Ok(Some(Input::Chunk(buf.split_to(read_to), self.bin_remain)))
       }
+      CodecState::RawChunks => {
+        if buf.is_empty() {
+          // Need more data
+          return Ok(None);
+        }
+
+        // Return everything that has been received so far.
+        Ok(Some(Input::RawChunk(buf.split())))
+      }
       CodecState::Bytes => {
         if buf.is_empty() {
           // Need more data
@@ -654,6 +2520,7 @@ impl Decoder for Codec {
         // When no more data is expected for this binary part, revert to
         // expecting Telegram lines
         self.state = CodecState::Telegram;
+        self.check_alignment = self.strict_binary_alignment;
 
         // Return a buffer and the amount of data remaining, this buffer
         // included.  The application can check if remain is 0 to determine
@@ -681,12 +2548,38 @@ impl Decoder for Codec {
         // When no more data is expected for this binary part, revert to
         // expecting Telegram lines
         self.state = CodecState::Telegram;
+        self.check_alignment = self.strict_binary_alignment;
 
         // Return a buffer and the amount of data remaining, this buffer
         // included.  The application can check if remain is 0 to determine
         // if it has received all the expected binary data.
         Ok(Some(Input::BytesMut(mem::take(&mut self.buf))))
       }
+      CodecState::Vec => {
+        if buf.is_empty() {
+          // Need more data
+          return Ok(None);
+        }
+        let read_to = cmp::min(self.bin_remain, buf.len());
+
+        // Transfer data from input to output buffer
+        self.buf.put(buf.split_to(read_to));
+
+        self.bin_remain -= read_to;
+        if self.bin_remain != 0 {
+          // Need more data
+          return Ok(None);
+        }
+
+        // When no more data is expected for this binary part, revert to
+        // expecting Telegram lines
+        self.state = CodecState::Telegram;
+        self.check_alignment = self.strict_binary_alignment;
+
+        // The output buffer is uniquely owned, so it is converted into a
+        // vector without copying.
+        Ok(Some(Input::Vec(mem::take(&mut self.buf).into())))
+      }
       CodecState::File | CodecState::Writer => {
         if buf.is_empty() {
           return Ok(None); // Need more data
@@ -729,9 +2622,89 @@ impl Decoder for Codec {
 
         // Revert to the default of expecting a telegram.
         self.state = CodecState::Telegram;
+        self.check_alignment = self.strict_binary_alignment;
 
         Ok(Some(ret))
       } // CodecState::{File|Writer}
+      CodecState::Sink => {
+        if buf.is_empty() {
+          return Ok(None); // Need more data
+        }
+
+        // Pass as much data as available or requested to the callback.
+        let read_to = cmp::min(self.bin_remain, buf.len());
+        let chunk = buf.split_to(read_to);
+        if let Some(ref mut f) = self.sink {
+          let f = f.get_mut().unwrap_or_else(PoisonError::into_inner);
+          f(&chunk);
+        }
+
+        self.bin_remain -= read_to;
+        if self.bin_remain != 0 {
+          return Ok(None); // Need more data
+        }
+
+        // Drop the callback
+        self.sink = None;
+
+        // Revert to the default of expecting a telegram.
+        self.state = CodecState::Telegram;
+        self.check_alignment = self.strict_binary_alignment;
+
+        Ok(Some(Input::SinkDone))
+      }
+      CodecState::Tee => {
+        if buf.is_empty() {
+          return Ok(None); // Need more data
+        }
+
+        // Write the received data to the writer, and keep a copy of it in
+        // the output buffer.
+        let read_to = cmp::min(self.bin_remain, buf.len());
+        let chunk = buf.split_to(read_to);
+        if let Some(ref mut f) = self.writer {
+          f.write_all(&chunk)?;
+        }
+        self.buf.put(chunk);
+
+        self.bin_remain -= read_to;
+        if self.bin_remain != 0 {
+          return Ok(None); // Need more data
+        }
+
+        // Close writer
+        self.writer = None;
+
+        // Revert to the default of expecting a telegram.
+        self.state = CodecState::Telegram;
+        self.check_alignment = self.strict_binary_alignment;
+
+        let bytesmut = mem::take(&mut self.buf);
+        Ok(Some(Input::Bytes(bytesmut.freeze())))
+      }
+      #[cfg(feature = "json")]
+      CodecState::Json => {
+        if buf.is_empty() {
+          // Need more data
+          return Ok(None);
+        }
+        let read_to = cmp::min(self.bin_remain, buf.len());
+
+        // Transfer data from input to output buffer
+        self.buf.put(buf.split_to(read_to));
+
+        self.bin_remain -= read_to;
+        if self.bin_remain != 0 {
+          // Need more data
+          return Ok(None);
+        }
+
+        // Revert to the default of expecting a telegram.
+        self.state = CodecState::Telegram;
+
+        let json = mem::take(&mut self.buf);
+        Ok(Some(Input::Params(json_to_params(&json)?)))
+      }
       CodecState::Skip => {
         if buf.is_empty() {
           return Ok(None); // Need more data
@@ -752,8 +2725,168 @@ impl Decoder for Codec {
 
         Ok(Some(Input::SkipDone))
       } // CodecState::Skip
+      CodecState::SkipLine => {
+        if let Some(idx) = self.get_eol_idx(buf)? {
+          buf.advance(idx);
+
+          // Revert to the default of expecting a telegram.
+          self.state = CodecState::Telegram;
+
+          Ok(Some(Input::SkipLineDone))
+        } else {
+          // Need more data
+          Ok(None)
+        }
+      }
+      CodecState::Greeting => {
+        if let Some(idx) = self.get_eol_idx(buf)? {
+          let line = buf.split_to(idx);
+          let line = &line[..line.len() - 1];
+          let line = utf8(without_carriage_return(line))?.to_string();
+
+          // Revert to the default of expecting a telegram.
+          self.state = CodecState::Telegram;
+
+          Ok(Some(Input::Greeting(line)))
+        } else {
+          // Need more data
+          Ok(None)
+        }
+      }
     } // match self.state
   }
+
+  /// Write a telegram's lines, any trailers and the terminating empty line
+  /// to a buffer.
+  fn encode_telegram(
+    &self,
+    tg: &Telegram,
+    buf: &mut BytesMut
+  ) -> Result<(), Error> {
+    #[cfg(feature = "hmac")]
+    let signed = self.hmac_key.is_some();
+    #[cfg(not(feature = "hmac"))]
+    let signed = false;
+
+    if self.integrity || signed {
+      let mut lines = BytesMut::new();
+      tg.encoder_write_lines(&mut lines)?;
+
+      #[cfg(feature = "hmac")]
+      if let Some(mac) = &self.hmac_key {
+        let mut mac = mac.clone();
+        mac.update(&lines);
+        let tag = mac.finalize().into_bytes();
+        let trailer = format!("{} {:x}\n", HMAC_KEY, tag);
+        lines.extend_from_slice(trailer.as_bytes());
+      }
+
+      if self.integrity {
+        let mut crc = Crc32::new();
+        crc.update(&lines);
+        let trailer = format!("{} {:08x}\n", CRC_KEY, crc.finish());
+        lines.extend_from_slice(trailer.as_bytes());
+      }
+
+      buf.reserve(lines.len() + 2);
+      buf.put(lines);
+    } else {
+      tg.encoder_write_lines(buf)?;
+    }
+    self.put_terminator(buf);
+    Ok(())
+  }
+}
+
+
+/// A Decoder implementation that is used to assist in decoding data arriving
+/// over a DDM client interface.
+///
+/// The default behavior for the Decoder is to wait for a Telegram buffer.  It
+/// will, on success, return an `Input::Telegram(tg)`, where `tg` is a
+/// `blather::Telegram` object.
+impl Decoder for Codec {
+  type Item = Input;
+  type Error = crate::err::Error;
+
+  fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Input>, Error> {
+    if self.paused {
+      return Ok(None);
+    }
+    if self.yield_after != 0 && self.yield_count >= self.yield_after {
+      // Force the Framed reader to go back to the underlying reader before
+      // any more frames are returned.
+      self.yield_count = 0;
+      return Ok(None);
+    }
+
+    let frame = self.decode_frame(buf)?;
+    if frame.is_some() {
+      self.yield_count += 1;
+    }
+    Ok(frame)
+  }
+
+  /// Called by the `Framed` reader once the underlying stream has been
+  /// closed.
+  ///
+  /// If the decoder has been told to read raw chunks until the end of the
+  /// stream, the stream ends cleanly.
+  ///
+  /// If the stream ends while a raw binary transfer is still in progress, or
+  /// with unprocessed data left in the input buffer, an `Error::Incomplete`
+  /// will be returned, so the application is able to distinguish a truncated
+  /// transfer from a clean shutdown.  The decoder will revert to expecting a
+  /// [`Telegram`], and any partially written file or writer will be closed.
+  fn decode_eof(
+    &mut self,
+    buf: &mut BytesMut
+  ) -> Result<Option<Input>, Error> {
+    if let Some(frame) = self.decode_frame(buf)? {
+      return Ok(Some(frame));
+    }
+
+    match self.state {
+      CodecState::Telegram
+      | CodecState::Params
+      | CodecState::ParamsCount
+      | CodecState::KVLines
+      | CodecState::TopicKVLines
+      | CodecState::SkipLine
+      | CodecState::Greeting => {
+        if buf.is_empty() {
+          Ok(None)
+        } else {
+          self.stats.errors += 1;
+          Err(Error::Incomplete("bytes remaining on stream".to_string()))
+        }
+      }
+      CodecState::RawChunks => {
+        // All the data has been returned; end the stream.
+        self.state = CodecState::Telegram;
+        Ok(None)
+      }
+      _ => {
+        // The decoder is in a binary state with data remaining, or it would
+        // already have reverted to expecting a telegram.
+        let remain = self.bin_remain;
+
+        self.writer = None;
+        self.sink = None;
+        self.pathname = None;
+        self.buf = BytesMut::new();
+        self.bin_remain = 0;
+        self.state = CodecState::Telegram;
+        self.update_transfer();
+        self.stats.errors += 1;
+
+        Err(Error::Incomplete(format!(
+          "stream ended with {} bytes of binary data remaining",
+          remain
+        )))
+      }
+    }
+  }
 }
 
 
@@ -765,8 +2898,29 @@ impl Encoder<&Telegram> for Codec {
     tg: &Telegram,
     buf: &mut BytesMut
   ) -> Result<(), Error> {
-    tg.encoder_write(buf)?;
-    Ok(())
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+      topic = tg.get_topic().unwrap_or_default(),
+      bytes = tg.calc_buf_size(),
+      "Encoding telegram"
+    );
+
+    match self.framing {
+      Framing::Lines => self.encode_telegram(tg, buf),
+      Framing::LengthPrefixed => {
+        let mut frame = BytesMut::new();
+        self.encode_telegram(tg, &mut frame)?;
+
+        let len = u32::try_from(frame.len()).map_err(|_| {
+          Error::InvalidSize("Telegram exceeds length prefix".to_string())
+        })?;
+
+        buf.reserve(4 + frame.len());
+        buf.put_u32(len);
+        buf.put(frame);
+        Ok(())
+      }
+    }
   }
 }
 
@@ -779,7 +2933,8 @@ impl Encoder<&Params> for Codec {
     params: &Params,
     buf: &mut BytesMut
   ) -> Result<(), Error> {
-    params.encoder_write(buf)?;
+    params.encoder_write_lines(buf)?;
+    self.put_terminator(buf);
     Ok(())
   }
 }
@@ -800,8 +2955,8 @@ impl Encoder<&HashMap<String, String>> for Codec {
       sz += k.len() + 1 + v.len() + 1;
     }
 
-    // Terminating empty line
-    sz += 1;
+    // Terminating line
+    sz += 2;
 
     //println!("Writing {} bin data", data.len());
     buf.reserve(sz);
@@ -812,7 +2967,7 @@ impl Encoder<&HashMap<String, String>> for Codec {
       buf.put(v.as_bytes());
       buf.put_u8(b'\n');
     }
-    buf.put_u8(b'\n');
+    self.put_terminator(buf);
 
     Ok(())
   }
@@ -827,7 +2982,30 @@ impl Encoder<&KVLines> for Codec {
     kvlines: &KVLines,
     buf: &mut BytesMut
   ) -> Result<(), Error> {
-    kvlines.encoder_write(buf)?;
+    kvlines.encoder_write_lines(buf)?;
+    self.put_terminator(buf);
+    Ok(())
+  }
+}
+
+
+/// Encode a topic line followed by a [`KVLines`] buffer.  This is an ordered
+/// counterpart to encoding a [`Telegram`], and is decoded using
+/// [`Codec::expect_topic_kvlines()`].
+impl Encoder<(&str, &KVLines)> for Codec {
+  type Error = crate::err::Error;
+
+  fn encode(
+    &mut self,
+    (topic, kvlines): (&str, &KVLines),
+    buf: &mut BytesMut
+  ) -> Result<(), Error> {
+    validate_topic(topic)?;
+    buf.reserve(topic.len() + 1);
+    buf.put(topic.as_bytes());
+    buf.put_u8(b'\n');
+    kvlines.encoder_write_lines(buf)?;
+    self.put_terminator(buf);
     Ok(())
   }
 }
@@ -0,0 +1,58 @@
+//! Incremental CRC-32 (IEEE 802.3) checksum calculation.
+
+const fn make_table() -> [u32; 256] {
+  let mut table = [0u32; 256];
+  let mut i = 0;
+  while i < 256 {
+    let mut c = i as u32;
+    let mut k = 0;
+    while k < 8 {
+      c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+      k += 1;
+    }
+    table[i] = c;
+    i += 1;
+  }
+  table
+}
+
+static TABLE: [u32; 256] = make_table();
+
+/// Running CRC-32 state.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Crc32(u32);
+
+impl Crc32 {
+  /// Create a new CRC-32 state.
+  pub(crate) fn new() -> Self {
+    Crc32(!0)
+  }
+
+  /// Feed data into the checksum.
+  pub(crate) fn update(&mut self, data: &[u8]) {
+    for b in data {
+      self.0 = TABLE[((self.0 ^ *b as u32) & 0xff) as usize] ^ (self.0 >> 8);
+    }
+  }
+
+  /// Get the checksum of all the data fed so far.
+  pub(crate) fn finish(&self) -> u32 {
+    !self.0
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::Crc32;
+
+  #[test]
+  fn check_value() {
+    let mut crc = Crc32::new();
+    crc.update(b"1234");
+    crc.update(b"56789");
+    assert_eq!(crc.finish(), 0xcbf4_3926);
+  }
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
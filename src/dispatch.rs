@@ -0,0 +1,89 @@
+//! Route [`Telegram`]s to handlers based on their topics.
+//!
+//! ```
+//! use blather::{Telegram, TopicDispatcher};
+//!
+//! let mut dispatcher = TopicDispatcher::new();
+//!
+//! dispatcher.register("Ping", |tg| {
+//!   assert_eq!(tg.get_topic(), Some("Ping"));
+//! });
+//!
+//! let tg = Telegram::new_topic("Ping").unwrap();
+//! dispatcher.dispatch(tg).unwrap();
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::err::Error;
+use crate::Telegram;
+
+/// A table of telegram handlers, keyed by telegram topics.
+#[derive(Default)]
+pub struct TopicDispatcher {
+  handlers: HashMap<String, Box<dyn FnMut(Telegram)>>
+}
+
+impl fmt::Debug for TopicDispatcher {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut topics: Vec<&String> = self.handlers.keys().collect();
+    topics.sort();
+    f.debug_struct("TopicDispatcher")
+      .field("topics", &topics)
+      .finish()
+  }
+}
+
+impl TopicDispatcher {
+  /// Create a new dispatcher without any registered handlers.
+  pub fn new() -> Self {
+    TopicDispatcher {
+      ..Default::default()
+    }
+  }
+
+  /// Register a handler for a topic.
+  ///
+  /// If a handler has already been registered for the topic it will be
+  /// replaced.
+  pub fn register<F>(&mut self, topic: &str, handler: F)
+  where
+    F: FnMut(Telegram) + 'static
+  {
+    self.handlers.insert(topic.to_string(), Box::new(handler));
+  }
+
+  /// Remove the handler for a topic.  Returns `true` if a handler was
+  /// registered for the topic.
+  pub fn unregister(&mut self, topic: &str) -> bool {
+    self.handlers.remove(topic).is_some()
+  }
+
+  /// Returns `true` if a handler has been registered for `topic`.
+  pub fn have(&self, topic: &str) -> bool {
+    self.handlers.contains_key(topic)
+  }
+
+  /// Pass a telegram to the handler registered for its topic.
+  ///
+  /// Returns `Error::KeyNotFound` if no handler has been registered for the
+  /// telegram's topic, and `Error::BadFormat` if the telegram doesn't have a
+  /// topic.
+  pub fn dispatch(&mut self, tg: Telegram) -> Result<(), Error> {
+    let topic = match tg.get_topic() {
+      Some(topic) => topic,
+      None => return Err(Error::BadFormat("Missing topic".to_string()))
+    };
+
+    match self.handlers.get_mut(topic) {
+      Some(handler) => {
+        handler(tg);
+        Ok(())
+      }
+      None => Err(Error::KeyNotFound(topic.to_string()))
+    }
+  }
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -1,8 +1,7 @@
 //! Error types and error management functions.
 
 use std::fmt;
-
-use tokio::io;
+use std::io;
 
 /// Error that `blather` can emit.
 #[derive(Debug, PartialEq)]
@@ -23,14 +22,83 @@ pub enum Error {
   BadState(String),
 
   /// The specified size is invalid, or invalid in a specific context.
-  InvalidSize(String)
+  InvalidSize(String),
+
+  /// The input stream ended before an expected buffer had been completely
+  /// received.
+  Incomplete(String),
+
+  /// A configured limit, such as the maximum number of parameters, has been
+  /// exceeded.  The string describes which limit was exceeded.
+  LimitExceeded(String)
+}
+
+impl Error {
+  /// Prefix the error's message with a context description, preserving the
+  /// error variant.
+  ///
+  /// ```
+  /// use blather::Error;
+  ///
+  /// let e = Error::BadFormat("Invalid key character".to_string());
+  /// let e = e.with_context("conn 7");
+  /// assert_eq!(e.to_string(), "Bad format; conn 7: Invalid key character");
+  /// ```
+  ///
+  /// # Notes
+  /// - For `Error::KeyNotFound` the context becomes a part of the string
+  ///   which otherwise only contains the key.
+  pub fn with_context(self, ctx: &str) -> Error {
+    let wrap = |s: String| format!("{}: {}", ctx, s);
+    match self {
+      Error::KeyNotFound(s) => Error::KeyNotFound(wrap(s)),
+      Error::BadFormat(s) => Error::BadFormat(wrap(s)),
+      Error::SerializeError(s) => Error::SerializeError(wrap(s)),
+      Error::IO(s) => Error::IO(wrap(s)),
+      Error::BadState(s) => Error::BadState(wrap(s)),
+      Error::InvalidSize(s) => Error::InvalidSize(wrap(s)),
+      Error::Incomplete(s) => Error::Incomplete(wrap(s)),
+      Error::LimitExceeded(s) => Error::LimitExceeded(wrap(s))
+    }
+  }
+
+  /// Returns `true` if the error is a protocol-level error which does not
+  /// necessarily require the connection to be torn down.
+  ///
+  /// `KeyNotFound`, `BadFormat`, `SerializeError`, `InvalidSize` and
+  /// `LimitExceeded` are classified as recoverable, while `IO`, `BadState`
+  /// and `Incomplete` are not.
+  ///
+  /// ```
+  /// use blather::Error;
+  ///
+  /// assert!(Error::BadFormat("Invalid topic".to_string()).is_recoverable());
+  /// assert!(!Error::IO("Connection reset".to_string()).is_recoverable());
+  /// ```
+  ///
+  /// # Notes
+  /// - An error returned by the `Decoder` still terminates a `Framed`
+  ///   stream.  Use [`Codec::set_resync_on_error()`] to continue decoding
+  ///   after malformed telegrams.
+  ///
+  /// [`Codec::set_resync_on_error()`]: crate::Codec::set_resync_on_error
+  pub fn is_recoverable(&self) -> bool {
+    match self {
+      Error::KeyNotFound(_)
+      | Error::BadFormat(_)
+      | Error::SerializeError(_)
+      | Error::InvalidSize(_)
+      | Error::LimitExceeded(_) => true,
+      Error::IO(_) | Error::BadState(_) | Error::Incomplete(_) => false
+    }
+  }
 }
 
 impl std::error::Error for Error {}
 
 impl fmt::Display for Error {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    match &*self {
+    match self {
       Error::KeyNotFound(s) => write!(f, "Parameter '{}' not found", s),
       Error::BadFormat(s) => write!(f, "Bad format; {}", s),
       Error::SerializeError(s) => write!(f, "Unable to serialize; {}", s),
@@ -38,7 +106,9 @@ impl fmt::Display for Error {
       Error::BadState(s) => {
         write!(f, "Encountred an unexpected/bad state: {}", s)
       }
-      Error::InvalidSize(s) => write!(f, "Invalid size; {}", s)
+      Error::InvalidSize(s) => write!(f, "Invalid size; {}", s),
+      Error::Incomplete(s) => write!(f, "Incomplete input; {}", s),
+      Error::LimitExceeded(s) => write!(f, "Limit exceeded; {}", s)
     }
   }
 }
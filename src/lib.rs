@@ -50,17 +50,59 @@
 //! [`Framed`](tokio_util::codec::Framed) framework, by
 //! implementing its own [`Codec`](codec::Codec).  It can be used to send and
 //! receive the various communication buffers supported by the crate.
+//!
+//! # Features
+//! The `codec` feature, which is enabled by default, enables the [`codec`]
+//! module and pulls in its `tokio` and `tokio-util` dependencies.  Disable
+//! the default features to only get the communication buffer types:
+//!
+//! ```toml
+//! [dependencies]
+//! blather = { version = "0.8", default-features = false }
+//! ```
+//!
+//! The `json` feature adds [`Codec::expect_json()`](codec::Codec::expect_json)
+//! which decodes a JSON object into a [`Params`] buffer.
+//!
+//! The `derive` feature adds a `#[derive(FromTelegram)]` macro, which
+//! implements [`FromTelegram`] for structs by reading each field from the
+//! telegram parameter of the same name.
+//!
+//! The `tracing` feature makes the [`Codec`](codec::Codec) emit
+//! [`tracing`](https://docs.rs/tracing) events when telegrams and other
+//! buffers are decoded or encoded, when binary transfers begin and end, and
+//! when decoding fails.
 
 #![deny(missing_docs)]
 #![deny(missing_crate_level_docs)]
 #![deny(missing_doc_code_examples)]
 
+#[cfg(feature = "codec")]
 pub mod codec;
+#[cfg(feature = "codec")]
+mod crc32;
+pub mod dispatch;
 mod err;
 pub mod types;
 
+/// Validators for telegram topics and parameter keys.
+///
+/// These are the validators used when topics and keys are added to buffers,
+/// and can be used to check user input before any buffers are constructed.
+pub mod validate {
+  pub use crate::types::validators::{validate_param_key, validate_topic};
+}
+
+#[cfg(feature = "codec")]
 pub use codec::Codec;
+pub use dispatch::TopicDispatcher;
 pub use err::Error;
-pub use types::{KVLines, KeyValue, Params, Telegram};
+pub use types::{
+  Encoding, FromTelegram, KVLines, KeyPolicy, KeyValue, LineEnding, Params,
+  Telegram
+};
+
+#[cfg(feature = "derive")]
+pub use blather_derive::FromTelegram;
 
 // vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -1,14 +1,20 @@
 //! Collection of data types which can be sent/received using the internal
 //! [`Codec`](crate::codec::Codec)
 
+pub mod encoding;
 pub mod kvlines;
+pub mod lineending;
+pub(crate) mod lines;
 pub mod params;
 pub mod telegram;
 
-mod validators;
+pub(crate) mod validators;
 
-pub use kvlines::{KVLines, KeyValue};
-pub use params::Params;
-pub use telegram::Telegram;
+pub use encoding::Encoding;
+pub use kvlines::{DupPolicy, KVLines, KVLinesBuilder, KeyValue};
+pub use lineending::LineEnding;
+pub use params::{Params, ParamsDiff};
+pub use telegram::{FromTelegram, Telegram};
+pub use validators::KeyPolicy;
 
 // vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
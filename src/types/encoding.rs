@@ -0,0 +1,152 @@
+//! Encodings used to store binary data in the (utf-8 string) values of
+//! key/value buffers.
+
+use crate::err::Error;
+
+const B64_CHARS: &[u8; 64] =
+  b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+const HEX_CHARS: &[u8; 16] = b"0123456789abcdef";
+
+/// Text encoding used to represent binary data in a parameter value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+  /// Standard base64 alphabet, with padding.
+  Base64,
+
+  /// Hexadecimal, two characters per byte.  Lower case characters are used
+  /// when encoding, but either case is accepted when decoding.
+  Hex
+}
+
+/// Encode a binary buffer into a string using the requested encoding.
+pub(crate) fn encode(data: &[u8], enc: Encoding) -> String {
+  match enc {
+    Encoding::Base64 => encode_base64(data),
+    Encoding::Hex => encode_hex(data)
+  }
+}
+
+/// Decode a string, using the requested encoding, into a binary buffer.
+pub(crate) fn decode(s: &str, enc: Encoding) -> Result<Vec<u8>, Error> {
+  match enc {
+    Encoding::Base64 => decode_base64(s),
+    Encoding::Hex => decode_hex(s)
+  }
+}
+
+
+fn encode_base64(data: &[u8]) -> String {
+  let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+
+  for chunk in data.chunks(3) {
+    let b0 = chunk[0] as u32;
+    let b1 = chunk.get(1).copied().unwrap_or(0) as u32;
+    let b2 = chunk.get(2).copied().unwrap_or(0) as u32;
+    let n = (b0 << 16) | (b1 << 8) | b2;
+
+    out.push(B64_CHARS[(n >> 18) as usize & 0x3f] as char);
+    out.push(B64_CHARS[(n >> 12) as usize & 0x3f] as char);
+    if chunk.len() > 1 {
+      out.push(B64_CHARS[(n >> 6) as usize & 0x3f] as char);
+    } else {
+      out.push('=');
+    }
+    if chunk.len() > 2 {
+      out.push(B64_CHARS[n as usize & 0x3f] as char);
+    } else {
+      out.push('=');
+    }
+  }
+
+  out
+}
+
+fn b64_value(c: u8) -> Option<u32> {
+  match c {
+    b'A'..=b'Z' => Some((c - b'A') as u32),
+    b'a'..=b'z' => Some((c - b'a') as u32 + 26),
+    b'0'..=b'9' => Some((c - b'0') as u32 + 52),
+    b'+' => Some(62),
+    b'/' => Some(63),
+    _ => None
+  }
+}
+
+fn decode_base64(s: &str) -> Result<Vec<u8>, Error> {
+  let s = s.as_bytes();
+  if s.len() % 4 != 0 {
+    return Err(Error::BadFormat("Invalid base64 length".to_string()));
+  }
+
+  let mut out = Vec::with_capacity(s.len() / 4 * 3);
+  let nchunks = s.len() / 4;
+
+  for (i, chunk) in s.chunks(4).enumerate() {
+    // Padding is only allowed at the end of the final chunk
+    let pad = if i + 1 == nchunks {
+      chunk.iter().rev().take_while(|c| **c == b'=').count()
+    } else {
+      0
+    };
+    if pad > 2 {
+      return Err(Error::BadFormat("Invalid base64 padding".to_string()));
+    }
+
+    let mut n = 0;
+    for c in &chunk[..4 - pad] {
+      match b64_value(*c) {
+        Some(v) => n = (n << 6) | v,
+        None => {
+          return Err(Error::BadFormat(
+            "Invalid base64 character".to_string()
+          ));
+        }
+      }
+    }
+    n <<= 6 * pad as u32;
+
+    out.push((n >> 16) as u8);
+    if pad < 2 {
+      out.push((n >> 8) as u8);
+    }
+    if pad < 1 {
+      out.push(n as u8);
+    }
+  }
+
+  Ok(out)
+}
+
+
+fn encode_hex(data: &[u8]) -> String {
+  let mut out = String::with_capacity(data.len() * 2);
+  for b in data {
+    out.push(HEX_CHARS[(b >> 4) as usize] as char);
+    out.push(HEX_CHARS[(b & 0x0f) as usize] as char);
+  }
+  out
+}
+
+fn decode_hex(s: &str) -> Result<Vec<u8>, Error> {
+  let s = s.as_bytes();
+  if s.len() % 2 != 0 {
+    return Err(Error::BadFormat("Invalid hex length".to_string()));
+  }
+
+  let mut out = Vec::with_capacity(s.len() / 2);
+  for pair in s.chunks(2) {
+    let hi = (pair[0] as char).to_digit(16);
+    let lo = (pair[1] as char).to_digit(16);
+    match (hi, lo) {
+      (Some(hi), Some(lo)) => out.push((hi << 4 | lo) as u8),
+      _ => {
+        return Err(Error::BadFormat("Invalid hex character".to_string()));
+      }
+    }
+  }
+
+  Ok(out)
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
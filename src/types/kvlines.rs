@@ -3,7 +3,10 @@
 use std::convert::From;
 use std::fmt;
 
-use bytes::{BufMut, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
+
+use super::lineending::LineEnding;
+use super::params::Params;
 
 use crate::err::Error;
 
@@ -14,6 +17,37 @@ pub struct KeyValue {
   value: String
 }
 
+impl KeyValue {
+  /// Get the key of this entry.
+  pub fn key(&self) -> &str {
+    &self.key
+  }
+
+  /// Get the value of this entry.
+  pub fn value(&self) -> &str {
+    &self.value
+  }
+
+  /// Replace the value of this entry.
+  pub fn set_value<V: ToString>(&mut self, value: V) {
+    self.value = value.to_string();
+  }
+}
+
+/// Strategy used by [`KVLines::to_params()`] to handle keys which occur more
+/// than once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DupPolicy {
+  /// Keep the value of the first occurrence of a key.
+  KeepFirst,
+
+  /// Keep the value of the last occurrence of a key.
+  KeepLast,
+
+  /// Fail with an `Error::BadFormat` if a key occurs more than once.
+  Error
+}
+
 /// Ordered list of key/value pairs, with no uniqueness constraint for the
 /// keys.
 #[derive(Debug, Clone, Default)]
@@ -21,6 +55,51 @@ pub struct KVLines {
   lines: Vec<KeyValue>
 }
 
+/// Iterator over the key/value pairs of a [`KVLines`], in order.  Returned by
+/// [`KVLines::iter()`].
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+  inner: std::slice::Iter<'a, KeyValue>
+}
+
+impl<'a> Iterator for Iter<'a> {
+  type Item = (&'a str, &'a str);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.inner.next().map(|kv| (kv.key.as_str(), kv.value.as_str()))
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.inner.size_hint()
+  }
+}
+
+/// Builder used to construct a [`KVLines`] in a single expression.
+///
+/// ```
+/// use blather::KVLines;
+///
+/// let kvlines = KVLines::builder().line("a", "1").line("b", "2").build();
+/// assert_eq!(kvlines.serialize().unwrap(), b"a 1\nb 2\n\n");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KVLinesBuilder {
+  kvlines: KVLines
+}
+
+impl KVLinesBuilder {
+  /// Append a key/value entry to the end of the list.
+  pub fn line<T: ToString, U: ToString>(mut self, key: T, value: U) -> Self {
+    self.kvlines.append(key, value);
+    self
+  }
+
+  /// Return the constructed [`KVLines`].
+  pub fn build(self) -> KVLines {
+    self.kvlines
+  }
+}
+
 impl KVLines {
   /// Create a new empty parameters object.
   pub fn new() -> Self {
@@ -29,11 +108,57 @@ impl KVLines {
     }
   }
 
+  /// Create a [`KVLinesBuilder`], used to construct a `KVLines` using
+  /// chained calls.
+  pub fn builder() -> KVLinesBuilder {
+    KVLinesBuilder::default()
+  }
+
   /// Reset all the lines.
   pub fn clear(&mut self) {
     self.lines.clear();
   }
 
+  /// Return the number of key/value lines.
+  pub fn len(&self) -> usize {
+    self.lines.len()
+  }
+
+  /// Returns `true` if there are no key/value lines.
+  pub fn is_empty(&self) -> bool {
+    self.lines.is_empty()
+  }
+
+  /// Get an iterator over the key/value pairs, in order.
+  ///
+  /// ```
+  /// use blather::KVLines;
+  ///
+  /// let kvlines = KVLines::builder().line("a", "1").line("b", "2").build();
+  /// let keys: Vec<&str> = kvlines.iter().map(|(k, _)| k).collect();
+  /// assert_eq!(keys, vec!["a", "b"]);
+  /// ```
+  pub fn iter(&self) -> Iter<'_> {
+    Iter {
+      inner: self.lines.iter()
+    }
+  }
+
+  /// Get a mutable reference to the first entry with the key `key`, or
+  /// `None` if there is no such entry.
+  ///
+  /// ```
+  /// use blather::KVLines;
+  ///
+  /// let mut kvlines = KVLines::builder().line("a", "1").line("a", "2");
+  /// let mut kvlines = kvlines.build();
+  /// kvlines.get_mut("a").unwrap().set_value(3);
+  /// assert_eq!(kvlines.serialize().unwrap(), b"a 3\na 2\n\n");
+  /// ```
+  pub fn get_mut(&mut self, key: &str) -> Option<&mut KeyValue> {
+    self.lines.iter_mut().find(|kv| kv.key == key)
+  }
+
   /// Get a reference to the inner vector of [`KeyValue`]'s.
   pub fn get_inner(&self) -> &Vec<KeyValue> {
     &self.lines
@@ -47,6 +172,62 @@ impl KVLines {
     });
   }
 
+  /// Remove all entries with the key `key`.  Returns the number of entries
+  /// that were removed.
+  pub fn remove_key(&mut self, key: &str) -> usize {
+    let len = self.lines.len();
+    self.lines.retain(|kv| kv.key != key);
+    len - self.lines.len()
+  }
+
+  /// Retain only the entries for which the predicate `f` returns `true`.
+  /// The order of the retained entries is preserved.
+  pub fn retain<F: FnMut(&KeyValue) -> bool>(&mut self, f: F) {
+    self.lines.retain(f);
+  }
+
+  /// Get the key and a mutable reference to the value of the last entry.
+  #[cfg(feature = "codec")]
+  pub(crate) fn last_mut(&mut self) -> Option<(&str, &mut String)> {
+    self
+      .lines
+      .last_mut()
+      .map(|kv| (kv.key.as_str(), &mut kv.value))
+  }
+
+  /// Convert the key/value list into a [`Params`] buffer.  Since `Params`
+  /// keys are unique, `on_dup` determines how repeated keys are handled.
+  ///
+  /// Fails with `Error::BadFormat` if a key is not a valid `Params` key.
+  ///
+  /// ```
+  /// use blather::{KVLines, types::DupPolicy};
+  ///
+  /// let kvlines = KVLines::builder().line("a", "1").line("a", "2").build();
+  /// let params = kvlines.to_params(DupPolicy::KeepLast).unwrap();
+  /// assert_eq!(params.get_str("a"), Some("2"));
+  /// assert!(kvlines.to_params(DupPolicy::Error).is_err());
+  /// ```
+  pub fn to_params(&self, on_dup: DupPolicy) -> Result<Params, Error> {
+    let mut params = Params::new();
+    for kv in &self.lines {
+      if params.have(&kv.key) {
+        match on_dup {
+          DupPolicy::KeepFirst => continue,
+          DupPolicy::KeepLast => {}
+          DupPolicy::Error => {
+            return Err(Error::BadFormat(format!(
+              "Duplicate key '{}'",
+              kv.key
+            )));
+          }
+        }
+      }
+      params.add_str(&kv.key, &kv.value)?;
+    }
+    Ok(params)
+  }
+
   /// Calculate the size of the buffer in serialized form.
   /// Each entry will be a newline terminated utf-8 line.
   /// Last line will be a single newline character.
@@ -63,27 +244,74 @@ impl KVLines {
   /// Serialize object into a `Vec<u8>` buffer suitable for transmission.
   pub fn serialize(&self) -> Result<Vec<u8>, Error> {
     let mut buf = Vec::new();
+    self.serialize_into(&mut buf)?;
+    Ok(buf)
+  }
+
+
+  /// Serialize object into a `Vec<u8>` buffer, terminating each line using
+  /// the requested [`LineEnding`].
+  ///
+  /// ```
+  /// use blather::{KVLines, LineEnding};
+  ///
+  /// let mut kvlines = KVLines::new();
+  /// kvlines.append("Name", "Frank");
+  /// let buf = kvlines.serialize_with(LineEnding::CrLf).unwrap();
+  /// assert_eq!(buf, b"Name Frank\r\n\r\n");
+  /// ```
+  pub fn serialize_with(&self, ending: LineEnding) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    self.serialize_lines(&mut buf, ending);
+    Ok(buf)
+  }
+
+
+  /// Serialize `KVLines` and append the result to a caller-owned buffer.
+  pub fn serialize_into(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+    self.serialize_lines(buf, LineEnding::Lf);
+    Ok(())
+  }
+
+
+  /// Append the key/value lines and the terminating empty line to `buf`,
+  /// using the line ending `ending`.
+  fn serialize_lines(&self, buf: &mut Vec<u8>, ending: LineEnding) {
+    let eol = ending.as_bytes();
+    buf.reserve(self.calc_buf_size() + (eol.len() - 1) * (self.len() + 1));
 
     for n in &self.lines {
-      let k = n.key.as_bytes();
-      let v = n.value.as_bytes();
-      for a in k {
-        buf.push(*a);
-      }
+      buf.extend_from_slice(n.key.as_bytes());
       buf.push(b' ');
-      for a in v {
-        buf.push(*a);
-      }
-      buf.push(b'\n');
+      buf.extend_from_slice(n.value.as_bytes());
+      buf.extend_from_slice(eol);
     }
 
-    buf.push(b'\n');
+    buf.extend_from_slice(eol);
+  }
 
-    Ok(buf)
+  /// Encode the KVLines into an immutable buffer, in the same format as
+  /// [`encoder_write()`](Self::encoder_write).
+  pub fn to_bytes(&self) -> Result<Bytes, Error> {
+    let mut buf = BytesMut::with_capacity(self.calc_buf_size());
+    self.encoder_write(&mut buf)?;
+    Ok(buf.freeze())
   }
 
   /// Write the Params to a buffer.
   pub fn encoder_write(&self, buf: &mut BytesMut) -> Result<(), Error> {
+    self.encoder_write_lines(buf)?;
+    buf.put_u8(b'\n');
+
+    Ok(())
+  }
+
+  /// Write the key/value lines to a buffer, but not the terminating empty
+  /// line.
+  pub(crate) fn encoder_write_lines(
+    &self,
+    buf: &mut BytesMut
+  ) -> Result<(), Error> {
     // Calculate the required buffer size
     let size = self.calc_buf_size();
 
@@ -97,7 +325,6 @@ impl KVLines {
       buf.put(n.value.as_bytes());
       buf.put_u8(b'\n');
     }
-    buf.put_u8(b'\n');
 
     Ok(())
   }
@@ -109,6 +336,24 @@ impl KVLines {
   }
 }
 
+impl IntoIterator for KVLines {
+  type Item = KeyValue;
+  type IntoIter = std::vec::IntoIter<KeyValue>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.lines.into_iter()
+  }
+}
+
+impl<'a> IntoIterator for &'a KVLines {
+  type Item = (&'a str, &'a str);
+  type IntoIter = Iter<'a>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.iter()
+  }
+}
+
 impl From<Vec<KeyValue>> for KVLines {
   fn from(lines: Vec<KeyValue>) -> Self {
     KVLines { lines }
@@ -126,6 +371,24 @@ impl From<Vec<(String, String)>> for KVLines {
   }
 }
 
+/// Append key/value pairs from an iterator.
+impl Extend<(String, String)> for KVLines {
+  fn extend<I: IntoIterator<Item = (String, String)>>(&mut self, iter: I) {
+    self
+      .lines
+      .extend(iter.into_iter().map(|(key, value)| KeyValue { key, value }));
+  }
+}
+
+/// Append key/value pairs from an iterator.
+impl<'a> Extend<(&'a str, &'a str)> for KVLines {
+  fn extend<I: IntoIterator<Item = (&'a str, &'a str)>>(&mut self, iter: I) {
+    for (key, value) in iter {
+      self.append(key, value);
+    }
+  }
+}
+
 impl fmt::Display for KVLines {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     let mut kvlist = Vec::new();
@@ -0,0 +1,33 @@
+//! Line endings used when serializing line-based buffers.
+
+/// Character sequence which terminates each line of a serialized
+/// [`Telegram`](crate::Telegram), [`Params`](crate::Params) or
+/// [`KVLines`](crate::KVLines) buffer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineEnding {
+  /// A single newline character (`\n`).  This is the default.
+  #[default]
+  Lf,
+
+  /// A carriage return followed by a newline character (`\r\n`).
+  CrLf
+}
+
+impl LineEnding {
+  /// Return the byte sequence of the line ending.
+  ///
+  /// ```
+  /// use blather::LineEnding;
+  ///
+  /// assert_eq!(LineEnding::Lf.as_bytes(), b"\n");
+  /// assert_eq!(LineEnding::CrLf.as_bytes(), b"\r\n");
+  /// ```
+  pub fn as_bytes(&self) -> &'static [u8] {
+    match self {
+      LineEnding::Lf => b"\n",
+      LineEnding::CrLf => b"\r\n"
+    }
+  }
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
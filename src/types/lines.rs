@@ -0,0 +1,98 @@
+//! Helpers for parsing the line-based wire format outside of the codec.
+
+use crate::err::Error;
+
+/// Split a key/value line at the first space character.
+///
+/// Returns `None` if the line doesn't contain a space character, in which
+/// case the line should be ignored.
+pub(crate) fn split_kv(line: &str) -> Option<(&str, &str)> {
+  let idx = line.find(' ')?;
+  let (k, v) = line.split_at(idx);
+  Some((k, &v[1..]))
+}
+
+/// Split a key/value line at the first space or tab character.
+///
+/// Returns `None` if the line doesn't contain a space or tab character, in
+/// which case the line should be ignored.
+#[cfg(feature = "codec")]
+pub(crate) fn split_kv_any_ws(line: &str) -> Option<(&str, &str)> {
+  let idx = line.find([' ', '\t'])?;
+  let (k, v) = line.split_at(idx);
+  Some((k, &v[1..]))
+}
+
+/// Interpret a buffer as utf-8 and split it into lines, up until (but not
+/// including) the terminating empty line.  A trailing carriage return is
+/// stripped from each line.
+///
+/// Returns `Error::BadFormat` if the buffer is not valid utf-8, if the
+/// terminating empty line is missing or if there's data after it.
+pub(crate) fn block(buf: &[u8]) -> Result<Vec<&str>, Error> {
+  let s = std::str::from_utf8(buf).map_err(|_| {
+    Error::BadFormat("Unable to decode input as UTF8".to_string())
+  })?;
+
+  let mut lines = Vec::new();
+  let mut rest = s;
+  loop {
+    let idx = match rest.find('\n') {
+      Some(idx) => idx,
+      None => {
+        return Err(Error::BadFormat(
+          "Missing terminating empty line".to_string()
+        ));
+      }
+    };
+    let line = &rest[..idx];
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    rest = &rest[idx + 1..];
+
+    if line.is_empty() {
+      break;
+    }
+    lines.push(line);
+  }
+
+  if !rest.is_empty() {
+    return Err(Error::BadFormat(
+      "Unexpected data after terminating empty line".to_string()
+    ));
+  }
+
+  Ok(lines)
+}
+
+/// Split a string into lines, up until (but not including) an optional
+/// terminating empty line.  The final line does not need to be newline
+/// terminated.  A trailing carriage return is stripped from each line.
+///
+/// Returns `Error::BadFormat` if there's data after the terminating empty
+/// line.
+pub(crate) fn text_block(s: &str) -> Result<Vec<&str>, Error> {
+  let mut lines = Vec::new();
+  let mut rest = s;
+  while !rest.is_empty() {
+    let (line, next) = match rest.find('\n') {
+      Some(idx) => (&rest[..idx], &rest[idx + 1..]),
+      None => (rest, "")
+    };
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    rest = next;
+
+    if line.is_empty() {
+      if !rest.is_empty() {
+        return Err(Error::BadFormat(
+          "Unexpected data after terminating empty line".to_string()
+        ));
+      }
+      break;
+    }
+    lines.push(line);
+  }
+
+  Ok(lines)
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -3,14 +3,22 @@
 //! offers conventions for value layouts, such as comma-separated values for
 //! lists.
 
+use std::collections::hash_map;
 use std::collections::{HashMap, HashSet};
-use std::convert::From;
+use std::convert::{From, TryFrom};
 use std::fmt;
+use std::mem;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::Add;
 use std::str::FromStr;
 
-use bytes::{BufMut, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
 
-use super::validators::validate_param_key;
+use super::encoding::{self, Encoding};
+use super::kvlines::KVLines;
+use super::lineending::LineEnding;
+use super::lines;
+use super::validators::KeyPolicy;
 
 use crate::err::Error;
 
@@ -20,7 +28,70 @@ use crate::err::Error;
 /// Uses `String`s for both keys and values internally.
 #[derive(Debug, Clone, Default)]
 pub struct Params {
-  hm: HashMap<String, String>
+  hm: HashMap<String, String>,
+  policy: KeyPolicy
+}
+
+/// Differences between two [`Params`] buffers, as returned by
+/// [`Params::diff()`].
+///
+/// All lists are sorted by key.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParamsDiff {
+  /// Key/value pairs which only exist in the new buffer.
+  pub added: Vec<(String, String)>,
+
+  /// Key/value pairs which only exist in the old buffer.
+  pub removed: Vec<(String, String)>,
+
+  /// Keys which exist in both buffers, but with different values.  Each
+  /// entry is a `(key, old value, new value)` tuple.
+  pub changed: Vec<(String, String, String)>
+}
+
+impl ParamsDiff {
+  /// Returns `true` if there are no differences.
+  pub fn is_empty(&self) -> bool {
+    self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+  }
+}
+
+/// A view into a single parameter of a [`Params`] buffer, which may either
+/// be vacant or occupied.  Returned by [`Params::entry()`].
+pub struct Entry<'a> {
+  inner: hash_map::Entry<'a, String, String>
+}
+
+impl<'a> Entry<'a> {
+  /// Get the key of this entry.
+  pub fn key(&self) -> &str {
+    self.inner.key()
+  }
+
+  /// Insert `default` if the entry is vacant.  Returns a mutable reference
+  /// to the value.
+  pub fn or_insert<V: ToString>(self, default: V) -> &'a mut String {
+    self.inner.or_insert_with(|| default.to_string())
+  }
+
+  /// Insert the value returned by `f` if the entry is vacant.  Returns a
+  /// mutable reference to the value.
+  pub fn or_insert_with<F: FnOnce() -> String>(self, f: F) -> &'a mut String {
+    self.inner.or_insert_with(f)
+  }
+
+  /// Modify the value using `f` if the entry is occupied.
+  pub fn and_modify<F: FnOnce(&mut String)>(self, f: F) -> Self {
+    Entry {
+      inner: self.inner.and_modify(f)
+    }
+  }
+}
+
+impl fmt::Debug for Entry<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Entry").field("key", &self.key()).finish()
+  }
 }
 
 impl Params {
@@ -32,18 +103,133 @@ impl Params {
   }
 
 
+  /// Create a new empty parameters object which validates keys using a
+  /// custom [`KeyPolicy`].
+  ///
+  /// The policy applies to all methods that add keys, including keys added
+  /// via the `Extend` implementations.
+  pub fn new_with_policy(policy: KeyPolicy) -> Self {
+    Params {
+      hm: HashMap::new(),
+      policy
+    }
+  }
+
+
   /// Reset all the key/values in `Params` object.
+  ///
+  /// The allocated capacity is retained, so a cleared buffer can be reused
+  /// without reallocating.
   pub fn clear(&mut self) {
     self.hm.clear();
   }
 
 
+  /// Return the number of key/value pairs the parameter buffer can hold
+  /// without reallocating.
+  pub fn capacity(&self) -> usize {
+    self.hm.capacity()
+  }
+
+
+  /// Remove all key/value pairs from the parameter buffer, returning them as
+  /// an iterator of owned pairs in arbitrary order.
+  ///
+  /// As with [`clear()`](Self::clear), the allocated capacity is retained.
+  /// If the iterator is dropped before it has been fully consumed, the
+  /// remaining pairs are still removed.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_str("Name", "Frank").unwrap();
+  ///   let pairs: Vec<(String, String)> = params.drain().collect();
+  ///   assert_eq!(pairs, vec![("Name".to_string(), "Frank".to_string())]);
+  ///   assert!(params.is_empty());
+  /// }
+  /// ```
+  pub fn drain(&mut self) -> impl Iterator<Item = (String, String)> + '_ {
+    self.hm.drain()
+  }
+
+
+  /// Reserve capacity for at least `additional` more key/value pairs.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.reserve(16);
+  ///   assert!(params.capacity() >= 16);
+  ///   params.clear();
+  ///   assert!(params.capacity() >= 16);
+  /// }
+  /// ```
+  pub fn reserve(&mut self, additional: usize) {
+    self.hm.reserve(additional);
+  }
+
+
   /// Return the number of key/value pairs in the parameter buffer.
   pub fn len(&self) -> usize {
     self.hm.len()
   }
 
 
+  /// Returns `true` if the parameter buffer does not contain any key/value
+  /// pairs.
+  pub fn is_empty(&self) -> bool {
+    self.hm.is_empty()
+  }
+
+
+  /// Return an iterator over the keys of the parameter buffer, in arbitrary
+  /// order.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_param("Name", "Frank").unwrap();
+  ///   let keys: Vec<&str> = params.keys().collect();
+  ///   assert_eq!(keys, vec!["Name"]);
+  /// }
+  /// ```
+  pub fn keys(&self) -> impl Iterator<Item = &str> {
+    self.hm.keys().map(|k| k.as_str())
+  }
+
+
+  /// Return an iterator over the values of the parameter buffer, in
+  /// arbitrary order.
+  pub fn values(&self) -> impl Iterator<Item = &str> {
+    self.hm.values().map(|v| v.as_str())
+  }
+
+
+  /// Return the keys of the parameter buffer, sorted.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_param("moo", "cow").unwrap();
+  ///   params.add_param("foo", "bar").unwrap();
+  ///   assert_eq!(params.param_names(), vec!["foo", "moo"]);
+  /// }
+  /// ```
+  pub fn param_names(&self) -> Vec<&str> {
+    let mut names: Vec<&str> = self.keys().collect();
+    names.sort_unstable();
+    names
+  }
+
+
   /// Return reference to inner HashMap.
   pub fn get_inner(&self) -> &HashMap<String, String> {
     &self.hm
@@ -71,13 +257,74 @@ impl Params {
   ) -> Result<(), Error> {
     let key = key.to_string();
 
-    validate_param_key(&key)?;
+    self.policy.validate(&key)?;
 
     self.hm.insert(key, value.to_string());
     Ok(())
   }
 
 
+  /// Add a parameter to the parameter buffer, and return the value it
+  /// replaced, if any.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   assert_eq!(params.insert("Name", "Frank"), Ok(None));
+  ///   assert_eq!(
+  ///     params.insert("Name", "Chloe"),
+  ///     Ok(Some("Frank".to_string()))
+  ///   );
+  /// }
+  /// ```
+  pub fn insert<T: ToString, U: ToString>(
+    &mut self,
+    key: T,
+    value: U
+  ) -> Result<Option<String>, Error> {
+    let key = key.to_string();
+
+    self.policy.validate(&key)?;
+
+    Ok(self.hm.insert(key, value.to_string()))
+  }
+
+
+  /// Add a parameter to the parameter buffer, unless a parameter with the
+  /// same key already exists.  Returns `Ok(true)` if the parameter was added
+  /// and `Ok(false)` if the key already existed.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_param("Timeout", 10).unwrap();
+  ///   assert_eq!(params.set_param_if_absent("Timeout", 30), Ok(false));
+  ///   assert_eq!(params.set_param_if_absent("Retries", 3), Ok(true));
+  ///   assert_eq!(params.get_int::<u32>("Timeout").unwrap(), 10);
+  ///   assert_eq!(params.get_int::<u32>("Retries").unwrap(), 3);
+  /// }
+  /// ```
+  pub fn set_param_if_absent<T: ToString, U: ToString>(
+    &mut self,
+    key: T,
+    value: U
+  ) -> Result<bool, Error> {
+    let key = key.to_string();
+    if self.hm.contains_key(&key) {
+      return Ok(false);
+    }
+
+    self.policy.validate(&key)?;
+
+    self.hm.insert(key, value.to_string());
+    Ok(true)
+  }
+
+
   /// Add a string parameter to the parameter.
   ///
   /// # Notes
@@ -117,13 +364,43 @@ impl Params {
     I: IntoIterator<Item = S>,
     S: AsRef<str>
   {
-    let mut sv = Vec::new();
-    for o in c.into_iter() {
-      sv.push(o.as_ref().to_string());
-    }
-    self.add_param(key, sv.join(","))?;
+    self.add_strit_with(key, c, ',')
+  }
 
-    Ok(())
+
+  /// Add parameter where the value is generated from an iterator over
+  /// strings, where entries are separated by `sep`.
+  ///
+  /// The caller must make sure that the entries do not contain the separator
+  /// character.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_strit_with("Cats", &["Tom", "Felix"], '|').unwrap();
+  ///   assert_eq!(params.get_str("Cats"), Some("Tom|Felix"));
+  /// }
+  /// ```
+  pub fn add_strit_with<I, S>(
+    &mut self,
+    key: &str,
+    c: I,
+    sep: char
+  ) -> Result<(), Error>
+  where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>
+  {
+    let mut val = String::new();
+    for (i, o) in c.into_iter().enumerate() {
+      if i != 0 {
+        val.push(sep);
+      }
+      val.push_str(o.as_ref());
+    }
+    self.add_param(key, val)
   }
 
 
@@ -158,6 +435,270 @@ impl Params {
   }
 
 
+  /// Get the value of a parameter, or insert a value generated by a closure
+  /// if the parameter does not exist.
+  ///
+  /// The closure is only called if `key` does not exist, in which case `key`
+  /// is validated before the value is inserted.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   let v = params.get_or_insert_with("Name", || "Elena".to_string());
+  ///   assert_eq!(v, Ok("Elena"));
+  ///   let v = params.get_or_insert_with("Name", || "Chloe".to_string());
+  ///   assert_eq!(v, Ok("Elena"));
+  /// }
+  /// ```
+  pub fn get_or_insert_with<F>(
+    &mut self,
+    key: &str,
+    f: F
+  ) -> Result<&str, Error>
+  where
+    F: FnOnce() -> String
+  {
+    match self.hm.entry(key.to_string()) {
+      hash_map::Entry::Occupied(e) => Ok(e.into_mut()),
+      hash_map::Entry::Vacant(e) => {
+        self.policy.validate(key)?;
+        Ok(e.insert(f()))
+      }
+    }
+  }
+
+
+  /// Retain only the parameters for which the predicate `f`, called with
+  /// each key and value, returns `true`.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_str("Name", "Frank").unwrap();
+  ///   params.add_str("Password", "secret").unwrap();
+  ///   params.retain(|k, _| k != "Password");
+  ///   assert_eq!(params.param_names(), vec!["Name"]);
+  /// }
+  /// ```
+  pub fn retain<F: FnMut(&str, &str) -> bool>(&mut self, mut f: F) {
+    self.hm.retain(|k, v| f(k, v));
+  }
+
+
+  /// Rewrite every value in place by passing it through `f`.  Keys are left
+  /// untouched.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_str("Name", "  Frank ").unwrap();
+  ///   params.map_values(|v| v.trim().to_string());
+  ///   assert_eq!(params.get_str("Name"), Some("Frank"));
+  /// }
+  /// ```
+  pub fn map_values<F: Fn(&str) -> String>(&mut self, f: F) {
+    for v in self.hm.values_mut() {
+      *v = f(v);
+    }
+  }
+
+
+  /// Create a new buffer by passing each key and value through `f`.  If `f`
+  /// returns `None` the parameter is dropped, otherwise the returned
+  /// `(key, value)` pair is added to the new buffer.
+  ///
+  /// The new buffer uses this buffer's [`KeyPolicy`].  Returns
+  /// `Error::BadFormat` if a produced key is not accepted by it.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_str("name", "frank").unwrap();
+  ///   params.add_str("password", "secret").unwrap();
+  ///   let out = params
+  ///     .filter_map(|k, v| {
+  ///       (k != "password").then(|| (k.to_uppercase(), v.to_uppercase()))
+  ///     })
+  ///     .unwrap();
+  ///   assert_eq!(out.len(), 1);
+  ///   assert_eq!(out.get_str("NAME"), Some("FRANK"));
+  /// }
+  /// ```
+  pub fn filter_map<F>(&self, f: F) -> Result<Params, Error>
+  where
+    F: Fn(&str, &str) -> Option<(String, String)>
+  {
+    let mut out = Params::new_with_policy(self.policy.clone());
+    for (k, v) in &self.hm {
+      if let Some((key, value)) = f(k, v) {
+        out.add_param(key, value)?;
+      }
+    }
+    Ok(out)
+  }
+
+
+  /// Merge the parameters of `other` into this buffer.  Keys which only exist
+  /// in `other` are copied as-is, while the value of a key which exists in
+  /// both buffers is replaced by the return value of
+  /// `resolve(key, existing, incoming)`.
+  ///
+  /// Returns `Error::BadFormat` if a key of `other` is not accepted by this
+  /// buffer's [`KeyPolicy`].
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_str("Tags", "red").unwrap();
+  ///   let mut other = Params::new();
+  ///   other.add_str("Tags", "blue").unwrap();
+  ///   params
+  ///     .merge_with(&other, |_k, old, new| format!("{},{}", old, new))
+  ///     .unwrap();
+  ///   assert_eq!(params.get_str("Tags"), Some("red,blue"));
+  /// }
+  /// ```
+  pub fn merge_with<F>(
+    &mut self,
+    other: &Params,
+    resolve: F
+  ) -> Result<(), Error>
+  where
+    F: Fn(&str, &str, &str) -> String
+  {
+    for (k, v) in &other.hm {
+      let value = match self.hm.get(k) {
+        Some(existing) => resolve(k, existing, v),
+        None => v.clone()
+      };
+      self.add_param(k, value)?;
+    }
+    Ok(())
+  }
+
+
+  /// Compute the differences between this (old) buffer and `other` (new)
+  /// buffer.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut old = Params::new();
+  ///   old.add_str("Name", "Frank").unwrap();
+  ///   let mut new = old.clone();
+  ///   new.add_str("Name", "Anne").unwrap();
+  ///   let diff = old.diff(&new);
+  ///   assert_eq!(
+  ///     diff.changed,
+  ///     vec![("Name".to_string(), "Frank".to_string(), "Anne".to_string())]
+  ///   );
+  /// }
+  /// ```
+  pub fn diff(&self, other: &Params) -> ParamsDiff {
+    let mut diff = ParamsDiff::default();
+
+    for (k, v) in &self.hm {
+      match other.hm.get(k) {
+        Some(nv) if nv != v => {
+          diff.changed.push((k.clone(), v.clone(), nv.clone()))
+        }
+        Some(_) => {}
+        None => diff.removed.push((k.clone(), v.clone()))
+      }
+    }
+    for (k, v) in &other.hm {
+      if !self.hm.contains_key(k) {
+        diff.added.push((k.clone(), v.clone()));
+      }
+    }
+
+    diff.added.sort();
+    diff.removed.sort();
+    diff.changed.sort();
+
+    diff
+  }
+
+
+  /// Get the parameters below a dotted key path, with the path prefix
+  /// stripped from their keys.
+  ///
+  /// A parameter belongs to the path `prefix` if its key starts with
+  /// `prefix` followed by a `.` character.  An empty `prefix` returns a copy
+  /// of all parameters.
+  ///
+  /// The keys are matched in a single pass without recursion, so arbitrarily
+  /// deep paths are supported.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_str("db.primary.host", "localhost").unwrap();
+  ///   params.add_str("db.primary.port", "5432").unwrap();
+  ///   params.add_str("db.replica.host", "remote").unwrap();
+  ///   let primary = params.get_path("db.primary");
+  ///   assert_eq!(primary.len(), 2);
+  ///   assert_eq!(primary.get_str("host"), Some("localhost"));
+  ///   assert_eq!(primary.get_str("port"), Some("5432"));
+  /// }
+  /// ```
+  pub fn get_path(&self, prefix: &str) -> Params {
+    if prefix.is_empty() {
+      return self.clone();
+    }
+
+    let mut out = Params::new_with_policy(self.policy.clone());
+    for (k, v) in &self.hm {
+      let sub = k
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .filter(|sub| !sub.is_empty());
+      if let Some(sub) = sub {
+        out.hm.insert(sub.to_string(), v.clone());
+      }
+    }
+    out
+  }
+
+
+  /// Get the entry of a parameter for in-place manipulation.  Returns
+  /// `Error::BadFormat` if the key is invalid.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.entry("Count".to_string()).unwrap().or_insert(0);
+  ///   params
+  ///     .entry("Count".to_string())
+  ///     .unwrap()
+  ///     .and_modify(|v| *v = (v.parse::<u32>().unwrap() + 1).to_string())
+  ///     .or_insert(0);
+  ///   assert_eq!(params.get_int::<u32>("Count").unwrap(), 1);
+  /// }
+  /// ```
+  pub fn entry(&mut self, key: String) -> Result<Entry<'_>, Error> {
+    self.policy.validate(&key)?;
+    Ok(Entry {
+      inner: self.hm.entry(key)
+    })
+  }
+
+
   /// Returns `true` if the parameter with `key` exists.  Returns `false`
   /// otherwise.
   pub fn have(&self, key: &str) -> bool {
@@ -165,22 +706,171 @@ impl Params {
   }
 
 
-  /// Get a parameter and convert it to a requested type, fail if key isn't
-  /// found.
+  /// Returns `true` if every parameter in this buffer exists with an equal
+  /// value in `other`.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut required = Params::new();
+  ///   required.add_str("Mode", "fast").unwrap();
+  ///   let mut req = required.clone();
+  ///   req.add_str("Name", "Frank").unwrap();
+  ///   assert!(required.is_subset_of(&req));
+  ///   assert!(!req.is_subset_of(&required));
+  /// }
+  /// ```
+  pub fn is_subset_of(&self, other: &Params) -> bool {
+    self.hm.iter().all(|(k, v)| other.hm.get(k) == Some(v))
+  }
+
+
+  /// Returns `true` if all the parameters in `keys` exist.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_str("Name", "Frank").unwrap();
+  ///   params.add_str("Age", "42").unwrap();
+  ///   assert!(params.contains_all_keys(&["Name", "Age"]));
+  ///   assert!(!params.contains_all_keys(&["Name", "Email"]));
+  /// }
+  /// ```
+  pub fn contains_all_keys(&self, keys: &[&str]) -> bool {
+    keys.iter().all(|k| self.hm.contains_key(*k))
+  }
+
+
+  /// Create a `Params` buffer from a `HashMap`, validating all its keys.
+  ///
+  /// Returns `Error::BadFormat` if any key is invalid.  Unlike the
+  /// infallible `From<HashMap>` implementation, this guarantees that the
+  /// buffer can be transmitted.
+  ///
+  /// # Examples
+  /// ```
+  /// use std::collections::HashMap;
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut hm = HashMap::new();
+  ///   hm.insert("Name".to_string(), "Frank".to_string());
+  ///   let params = Params::try_from_map(hm).unwrap();
+  ///   assert_eq!(params.get_str("Name"), Some("Frank"));
+  /// }
+  /// ```
+  pub fn try_from_map(hm: HashMap<String, String>) -> Result<Self, Error> {
+    let policy = KeyPolicy::default();
+    for k in hm.keys() {
+      policy.validate(k)?;
+    }
+    Ok(Params { hm, policy })
+  }
+
+
+  /// Returns `true` if all keys are accepted by the buffer's [`KeyPolicy`].
+  ///
+  /// Keys are validated when they are added, so this can only return `false`
+  /// for buffers whose contents were not added through the validating
+  /// methods, such as buffers created using `From<HashMap>`.
+  ///
+  /// # Examples
+  /// ```
+  /// use std::collections::HashMap;
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut hm = HashMap::new();
+  ///   hm.insert("bad key".to_string(), "value".to_string());
+  ///   assert!(!Params::from(hm).is_valid());
+  /// }
+  /// ```
+  pub fn is_valid(&self) -> bool {
+    self.hm.keys().all(|k| self.policy.validate(k).is_ok())
+  }
+
+
+  /// Get a parameter and convert it to a requested type, fail if key isn't
+  /// found.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::{Params, Error};
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_param("arthur", 42);
+  ///   let fourtytwo = params.get_param::<u32>("arthur").unwrap();
+  ///   assert_eq!(fourtytwo, 42);
+  ///   let nonexist = params.get_param::<u32>("ford");
+  ///   assert_eq!(nonexist, Err(Error::KeyNotFound("ford".to_string())));
+  /// }
+  /// ```
+  pub fn get_param<T: FromStr>(&self, key: &str) -> Result<T, Error> {
+    if let Some(val) = self.get_str(key) {
+      if let Ok(v) = T::from_str(val) {
+        return Ok(v);
+      }
+      return Err(Error::BadFormat(format!(
+        "Unable to parse value from parameter '{}'",
+        key
+      )));
+    }
+    Err(Error::KeyNotFound(key.to_string()))
+  }
+
+
+  /// Get a parameter and convert it to a requested type, return a default
+  /// value if key isn't found.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   let val = params.get_param_def::<u32>("nonexist", 11);
+  ///   assert_eq!(val, Ok(11));
+  /// }
+  /// ```
+  pub fn get_param_def<T: FromStr>(
+    &self,
+    key: &str,
+    def: T
+  ) -> Result<T, Error> {
+    if let Some(val) = self.get_str(key) {
+      if let Ok(v) = T::from_str(val) {
+        return Ok(v);
+      }
+      return Err(Error::BadFormat(format!(
+        "Unable to parse value from parameter '{}'",
+        key
+      )));
+    }
+    Ok(def)
+  }
+
+
+  /// Get a parameter and convert it to a requested type, return a value
+  /// computed by `f` if the key isn't found.
+  ///
+  /// The closure is only called if the key does not exist.  If the key does
+  /// exist, but its value can not be parsed, an `Error::BadFormat` is
+  /// returned.
   ///
   /// # Examples
   /// ```
-  /// use blather::{Params, Error};
+  /// use blather::Params;
   /// fn main() {
   ///   let mut params = Params::new();
-  ///   params.add_param("arthur", 42);
-  ///   let fourtytwo = params.get_param::<u32>("arthur").unwrap();
-  ///   assert_eq!(fourtytwo, 42);
-  ///   let nonexist = params.get_param::<u32>("ford");
-  ///   assert_eq!(nonexist, Err(Error::KeyNotFound("ford".to_string())));
+  ///   let val = params.get_param_or::<u32, _>("nonexist", || 6 * 7);
+  ///   assert_eq!(val, Ok(42));
   /// }
   /// ```
-  pub fn get_param<T: FromStr>(&self, key: &str) -> Result<T, Error> {
+  pub fn get_param_or<T: FromStr, F: FnOnce() -> T>(
+    &self,
+    key: &str,
+    f: F
+  ) -> Result<T, Error> {
     if let Some(val) = self.get_str(key) {
       if let Ok(v) = T::from_str(val) {
         return Ok(v);
@@ -190,37 +880,90 @@ impl Params {
         key
       )));
     }
-    Err(Error::KeyNotFound(key.to_string()))
+    Ok(f())
   }
 
 
-  /// Get a parameter and convert it to a requested type, return a default
-  /// value if key isn't found.
+  /// Get a parameter and convert it to a requested type, whose text form is
+  /// one of the values in `allowed`.  Fail if the key isn't found.
+  ///
+  /// If the value can not be parsed, the returned `Error::BadFormat` lists
+  /// the allowed values.
   ///
   /// # Examples
   /// ```
-  /// use blather::Params;
+  /// use std::str::FromStr;
+  /// use blather::{Error, Params};
+  ///
+  /// enum Mode {
+  ///   Fast,
+  ///   Safe
+  /// }
+  ///
+  /// impl FromStr for Mode {
+  ///   type Err = ();
+  ///   fn from_str(s: &str) -> Result<Self, ()> {
+  ///     match s {
+  ///       "fast" => Ok(Mode::Fast),
+  ///       "safe" => Ok(Mode::Safe),
+  ///       _ => Err(())
+  ///     }
+  ///   }
+  /// }
+  ///
   /// fn main() {
   ///   let mut params = Params::new();
-  ///   let val = params.get_param_def::<u32>("nonexist", 11);
-  ///   assert_eq!(val, Ok(11));
+  ///   params.add_str("Mode", "slow").unwrap();
+  ///   let res = params.get_enum::<Mode>("Mode", &["fast", "safe"]);
+  ///   assert!(matches!(res, Err(Error::BadFormat(_))));
   /// }
   /// ```
-  pub fn get_param_def<T: FromStr>(
+  pub fn get_enum<T: FromStr>(
     &self,
     key: &str,
-    def: T
+    allowed: &[&str]
   ) -> Result<T, Error> {
     if let Some(val) = self.get_str(key) {
       if let Ok(v) = T::from_str(val) {
         return Ok(v);
       }
+      return Err(Error::BadFormat(format!(
+        "Invalid value '{}' for parameter '{}'; expected one of: {}",
+        val,
+        key,
+        allowed.join(", ")
+      )));
+    }
+    Err(Error::KeyNotFound(key.to_string()))
+  }
+
+
+  /// Get a parameter, with leading and trailing whitespace removed, and
+  /// convert it to a requested type.  Fail if the key isn't found.
+  ///
+  /// The stored value is not modified.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_param("Age", " 42 ").unwrap();
+  ///   assert!(params.get_param::<u32>("Age").is_err());
+  ///   assert_eq!(params.get_param_trimmed::<u32>("Age").unwrap(), 42);
+  /// }
+  /// ```
+  pub fn get_param_trimmed<T: FromStr>(&self, key: &str) -> Result<T, Error> {
+    if let Some(val) = self.get_str(key) {
+      if let Ok(v) = T::from_str(val.trim()) {
+        return Ok(v);
+      }
       return Err(Error::BadFormat(format!(
         "Unable to parse value from parameter '{}'",
         key
       )));
     }
-    Ok(def)
+    Err(Error::KeyNotFound(key.to_string()))
   }
 
 
@@ -327,6 +1070,73 @@ impl Params {
   }
 
 
+  /// Parse the values of the listed keys and return their sum.  The sum of
+  /// an empty list of keys is `T::default()`.
+  ///
+  /// Fails if any of the keys is missing or can not be parsed.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_param("rx", 100).unwrap();
+  ///   params.add_param("tx", 42).unwrap();
+  ///   assert_eq!(params.sum::<u64>(&["rx", "tx"]).unwrap(), 142);
+  /// }
+  /// ```
+  pub fn sum<T>(&self, keys: &[&str]) -> Result<T, Error>
+  where
+    T: FromStr + Add<Output = T> + Default
+  {
+    let mut sum = T::default();
+    for key in keys {
+      sum = sum + self.get_param::<T>(key)?;
+    }
+    Ok(sum)
+  }
+
+
+  /// Parse the values of the listed keys and return the largest one.
+  /// Returns `None` if `keys` is empty.
+  ///
+  /// Fails if any of the keys is missing or can not be parsed.
+  pub fn max_value<T>(&self, keys: &[&str]) -> Result<Option<T>, Error>
+  where
+    T: FromStr + PartialOrd
+  {
+    let mut max: Option<T> = None;
+    for key in keys {
+      let v = self.get_param::<T>(key)?;
+      match max {
+        Some(ref m) if *m >= v => {}
+        _ => max = Some(v)
+      }
+    }
+    Ok(max)
+  }
+
+
+  /// Parse the values of the listed keys and return the smallest one.
+  /// Returns `None` if `keys` is empty.
+  ///
+  /// Fails if any of the keys is missing or can not be parsed.
+  pub fn min_value<T>(&self, keys: &[&str]) -> Result<Option<T>, Error>
+  where
+    T: FromStr + PartialOrd
+  {
+    let mut min: Option<T> = None;
+    for key in keys {
+      let v = self.get_param::<T>(key)?;
+      match min {
+        Some(ref m) if *m <= v => {}
+        _ => min = Some(v)
+      }
+    }
+    Ok(min)
+  }
+
+
   /// Get a boolean value; return error if key wasn't found.
   pub fn get_bool(&self, key: &str) -> Result<bool, Error> {
     if let Some(v) = self.get_str(key) {
@@ -359,6 +1169,39 @@ impl Params {
   }
 
 
+  /// Get an IPv4 or IPv6 address.
+  ///
+  /// Returns `Error::KeyNotFound` if the key does not exist, and
+  /// `Error::BadFormat` if the value is not a valid address.
+  ///
+  /// # Examples
+  /// ```
+  /// use std::net::{IpAddr, Ipv4Addr};
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_str("Addr", "192.168.0.1").unwrap();
+  ///   assert_eq!(
+  ///     params.get_ip("Addr").unwrap(),
+  ///     IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1))
+  ///   );
+  /// }
+  /// ```
+  pub fn get_ip(&self, key: &str) -> Result<IpAddr, Error> {
+    self.get_param(key)
+  }
+
+
+  /// Get a socket address, consisting of an IPv4 or IPv6 address and a port,
+  /// such as `127.0.0.1:8080` or `[::1]:8080`.
+  ///
+  /// Returns `Error::KeyNotFound` if the key does not exist, and
+  /// `Error::BadFormat` if the value is not a valid socket address.
+  pub fn get_socket_addr(&self, key: &str) -> Result<SocketAddr, Error> {
+    self.get_param(key)
+  }
+
+
   /// Parse the value of a key as a comma-separated list of strings and return
   /// it.  Only non-empty entries are returned.
   ///
@@ -373,12 +1216,65 @@ impl Params {
   /// }
   /// ```
   pub fn get_strvec(&self, key: &str) -> Result<Vec<String>, Error> {
+    self.get_strvec_with(key, ',')
+  }
+
+
+  /// Parse the value of a key as a comma-separated list of strings and return
+  /// it.  If the key does not exist, return `def` instead.
+  ///
+  /// Unlike [`get_strvec()`](Self::get_strvec), this distinguishes a missing
+  /// key from a key with an empty value; the latter returns an empty vector.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_param("empty", "");
+  ///   let def = vec!["drake".to_string()];
+  ///   let sv = params.get_strvec_def("missing", def.clone()).unwrap();
+  ///   assert_eq!(sv, vec!["drake"]);
+  ///   let sv = params.get_strvec_def("empty", def).unwrap();
+  ///   assert!(sv.is_empty());
+  /// }
+  /// ```
+  pub fn get_strvec_def(
+    &self,
+    key: &str,
+    def: Vec<String>
+  ) -> Result<Vec<String>, Error> {
+    if !self.have(key) {
+      return Ok(def);
+    }
+    self.get_strvec(key)
+  }
+
+
+  /// Parse the value of a key as a list of strings separated by `sep` and
+  /// return it.  Only non-empty entries are returned.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_param("list", "a,b|c");
+  ///   let sv = params.get_strvec_with("list", '|').unwrap();
+  ///   assert_eq!(sv, vec!["a,b", "c"]);
+  /// }
+  /// ```
+  pub fn get_strvec_with(
+    &self,
+    key: &str,
+    sep: char
+  ) -> Result<Vec<String>, Error> {
     let mut ret = Vec::new();
 
     if let Some(v) = self.get_str(key) {
-      let split = v.split(',');
+      let split = v.split(sep);
       for s in split {
-        if s.len() != 0 {
+        if !s.is_empty() {
           ret.push(s.to_string());
         }
       }
@@ -410,7 +1306,7 @@ impl Params {
     if let Some(v) = self.get_str(key) {
       let split = v.split(',');
       for s in split {
-        if s.len() != 0 {
+        if !s.is_empty() {
           ret.insert(s.to_string());
         }
       }
@@ -420,6 +1316,117 @@ impl Params {
   }
 
 
+  /// Parse the value of a key as a comma-separated list of unique strings and
+  /// return them in a HashSet.  If the key does not exist, return `def`
+  /// instead.
+  ///
+  /// Unlike [`get_hashset()`](Self::get_hashset), this distinguishes a
+  /// missing key from a key with an empty value; the latter returns an empty
+  /// set.
+  pub fn get_hashset_def(
+    &self,
+    key: &str,
+    def: HashSet<String>
+  ) -> Result<HashSet<String>, Error> {
+    if !self.have(key) {
+      return Ok(def);
+    }
+    self.get_hashset(key)
+  }
+
+
+  /// Add a parameter whose value is a binary buffer, encoded as a string
+  /// using the requested [`Encoding`].
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::{Encoding, Params};
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_bytes("Data", &[0xde, 0xad], Encoding::Hex).unwrap();
+  ///   assert_eq!(params.get_str("Data"), Some("dead"));
+  /// }
+  /// ```
+  pub fn add_bytes<K: ToString>(
+    &mut self,
+    key: K,
+    data: &[u8],
+    enc: Encoding
+  ) -> Result<(), Error> {
+    self.add_param(key, encoding::encode(data, enc))
+  }
+
+
+  /// Get a parameter and decode it into a binary buffer using the requested
+  /// [`Encoding`].
+  ///
+  /// Returns `Error::BadFormat` if the value could not be decoded.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::{Encoding, Params};
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_param("Data", "aGVsbG8=");
+  ///   let data = params.get_bytes("Data", Encoding::Base64).unwrap();
+  ///   assert_eq!(data, b"hello");
+  /// }
+  /// ```
+  pub fn get_bytes(&self, key: &str, enc: Encoding) -> Result<Vec<u8>, Error> {
+    if let Some(val) = self.get_str(key) {
+      return encoding::decode(val, enc);
+    }
+    Err(Error::KeyNotFound(key.to_string()))
+  }
+
+
+  /// Get the number of bytes this Params buffer occupies on the wire.
+  ///
+  /// Each parameter occupies one line, consisting of the key, a space
+  /// character, the value and a newline character.  The buffer is terminated
+  /// by an empty line, which is a single newline character.
+  ///
+  /// # Notes
+  /// - The size does not include any additional framing which may be added
+  ///   by the [`Codec`](crate::codec::Codec), such as alternate block
+  ///   terminators.
+  pub fn wire_size(&self) -> usize {
+    self.calc_buf_size()
+  }
+
+
+  /// Return the sum of the lengths, in bytes, of all values.
+  pub fn sum_values_len(&self) -> usize {
+    self.hm.values().map(|v| v.len()).sum()
+  }
+
+
+  /// Return an estimate of the number of bytes of heap memory used by the
+  /// buffer.
+  ///
+  /// The estimate consists of the allocated capacity of all keys and values,
+  /// and the memory of the hash table's slots, including the slots which are
+  /// currently unused.  Allocator overhead is not included.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   let empty = params.approx_heap_size();
+  ///   params.add_str("Name", "Frank").unwrap();
+  ///   assert!(params.approx_heap_size() > empty);
+  /// }
+  /// ```
+  pub fn approx_heap_size(&self) -> usize {
+    // Each slot holds a key and a value, and a control byte.
+    let slot = 2 * mem::size_of::<String>() + 1;
+    let strings: usize =
+      self.hm.iter().map(|(k, v)| k.capacity() + v.capacity()).sum();
+    self.hm.capacity() * slot + strings
+  }
+
+
   /// Calculate the size of the buffer in serialized form.
   /// Each entry will be a newline terminated utf-8 line.
   /// Last line will be a single newline character.
@@ -436,28 +1443,78 @@ impl Params {
   /// Serialize `Params` buffer into a vector of bytes for transmission.
   pub fn serialize(&self) -> Result<Vec<u8>, Error> {
     let mut buf = Vec::new();
+    self.serialize_into(&mut buf)?;
+    Ok(buf)
+  }
+
+
+  /// Serialize `Params` buffer into a vector of bytes, terminating each line
+  /// using the requested [`LineEnding`].
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::{LineEnding, Params};
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_str("Name", "Frank").unwrap();
+  ///   let buf = params.serialize_with(LineEnding::CrLf).unwrap();
+  ///   assert_eq!(buf, b"Name Frank\r\n\r\n");
+  /// }
+  /// ```
+  pub fn serialize_with(&self, ending: LineEnding) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    self.serialize_lines(&mut buf, ending);
+    Ok(buf)
+  }
+
+
+  /// Serialize `Params` and append the result to a caller-owned buffer.
+  pub fn serialize_into(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+    self.serialize_lines(buf, LineEnding::Lf);
+    Ok(())
+  }
+
+
+  /// Append the key/value lines and the terminating empty line to `buf`,
+  /// using the line ending `ending`.
+  fn serialize_lines(&self, buf: &mut Vec<u8>, ending: LineEnding) {
+    let eol = ending.as_bytes();
+    buf.reserve(self.calc_buf_size() + (eol.len() - 1) * (self.len() + 1));
 
     for (key, value) in &self.hm {
-      let k = key.as_bytes();
-      let v = value.as_bytes();
-      for a in k {
-        buf.push(*a);
-      }
+      buf.extend_from_slice(key.as_bytes());
       buf.push(b' ');
-      for a in v {
-        buf.push(*a);
-      }
-      buf.push(b'\n');
+      buf.extend_from_slice(value.as_bytes());
+      buf.extend_from_slice(eol);
     }
 
-    buf.push(b'\n');
+    buf.extend_from_slice(eol);
+  }
+
 
-    Ok(buf)
+  /// Encode the Params into an immutable buffer, in the same format as
+  /// [`encoder_write()`](Self::encoder_write).
+  pub fn to_bytes(&self) -> Result<Bytes, Error> {
+    let mut buf = BytesMut::with_capacity(self.calc_buf_size());
+    self.encoder_write(&mut buf)?;
+    Ok(buf.freeze())
   }
 
 
   /// Write the Params to a buffer.
   pub fn encoder_write(&self, buf: &mut BytesMut) -> Result<(), Error> {
+    self.encoder_write_lines(buf)?;
+    buf.put_u8(b'\n');
+
+    Ok(())
+  }
+
+  /// Write the key/value lines to a buffer, but not the terminating empty
+  /// line.
+  pub(crate) fn encoder_write_lines(
+    &self,
+    buf: &mut BytesMut
+  ) -> Result<(), Error> {
     // Calculate the required buffer size
     let size = self.calc_buf_size();
 
@@ -471,20 +1528,128 @@ impl Params {
       buf.put(value.as_bytes());
       buf.put_u8(b'\n');
     }
-    buf.put_u8(b'\n');
 
     Ok(())
   }
 
+  /// Consume the Params buffer and return its key/value pairs as a
+  /// [`KVLines`], sorted by key.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Params;
+  /// fn main() {
+  ///   let mut params = Params::new();
+  ///   params.add_param("b", "2").unwrap();
+  ///   params.add_param("a", "1").unwrap();
+  ///   let kvlines = params.into_kvlines_sorted();
+  ///   assert_eq!(kvlines.serialize().unwrap(), b"a 1\nb 2\n\n");
+  /// }
+  /// ```
+  pub fn into_kvlines_sorted(self) -> KVLines {
+    self.into_kvlines_with_order(&[])
+  }
+
+
+  /// Consume the Params buffer and return its key/value pairs as a
+  /// [`KVLines`].  The keys listed in `order` come first, in the listed
+  /// order, followed by all remaining keys sorted.
+  ///
+  /// Keys in `order` which do not exist in the Params buffer are ignored.
+  pub fn into_kvlines_with_order(self, order: &[&str]) -> KVLines {
+    let mut hm = self.hm;
+    let mut lines = Vec::with_capacity(hm.len());
+
+    for key in order {
+      if let Some((k, v)) = hm.remove_entry(*key) {
+        lines.push((k, v));
+      }
+    }
+
+    let mut rest: Vec<(String, String)> = hm.into_iter().collect();
+    rest.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    lines.extend(rest);
+
+    KVLines::from(lines)
+  }
+
+
   /// Consume the Params buffer and return its internal HashMap.
   pub fn into_inner(self) -> HashMap<String, String> {
     self.hm
   }
 }
 
+/// Create a `Params` buffer from a `HashMap`, without validating its keys.
+///
+/// Keys which are not valid parameter keys will cause a corrupt wire format
+/// if the buffer is transmitted.  Use [`Params::try_from_map()`] for maps
+/// whose keys have not already been validated, or check the resulting buffer
+/// using [`Params::is_valid()`].
 impl From<HashMap<String, String>> for Params {
   fn from(hm: HashMap<String, String>) -> Self {
-    Params { hm }
+    Params {
+      hm,
+      policy: KeyPolicy::default()
+    }
+  }
+}
+
+/// Add key/value pairs from an iterator.
+///
+/// # Notes
+/// - Pairs whose keys are not valid parameter keys are silently skipped.  Use
+///   [`Params::add_param()`] to detect invalid keys.
+impl Extend<(String, String)> for Params {
+  fn extend<I: IntoIterator<Item = (String, String)>>(&mut self, iter: I) {
+    for (key, value) in iter {
+      if self.policy.validate(&key).is_ok() {
+        self.hm.insert(key, value);
+      }
+    }
+  }
+}
+
+/// Add key/value pairs from an iterator.
+///
+/// # Notes
+/// - Pairs whose keys are not valid parameter keys are silently skipped.  Use
+///   [`Params::add_param()`] to detect invalid keys.
+impl<'a> Extend<(&'a str, &'a str)> for Params {
+  fn extend<I: IntoIterator<Item = (&'a str, &'a str)>>(&mut self, iter: I) {
+    for (key, value) in iter {
+      let _ = self.add_param(key, value);
+    }
+  }
+}
+
+/// Parse a serialized `Params` buffer.
+///
+/// The buffer must consist of key/value lines, where the key and the value
+/// are separated by the first space character, followed by a terminating
+/// empty line.  Lines without a space character are ignored, just like the
+/// [`Codec`](crate::codec::Codec) does.
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use blather::Params;
+/// fn main() {
+///   let params = Params::try_from(&b"Name Frank\nAge 42\n\n"[..]).unwrap();
+///   assert_eq!(params.get_str("Name"), Some("Frank"));
+///   assert_eq!(params.get_param::<u8>("Age").unwrap(), 42);
+/// }
+/// ```
+impl TryFrom<&[u8]> for Params {
+  type Error = Error;
+
+  fn try_from(buf: &[u8]) -> Result<Self, Self::Error> {
+    let mut params = Params::new();
+    for line in lines::block(buf)? {
+      if let Some((k, v)) = lines::split_kv(line) {
+        params.add_param(k, v)?;
+      }
+    }
+    Ok(params)
   }
 }
 
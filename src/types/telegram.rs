@@ -4,12 +4,15 @@
 
 use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::ops::{Deref, DerefMut};
 use std::str::FromStr;
 
-use bytes::{BufMut, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
 
 use crate::err::Error;
 
+use super::lineending::LineEnding;
+use super::lines;
 use super::params::Params;
 use super::validators::validate_topic;
 
@@ -56,6 +59,63 @@ impl Telegram {
   }
 
 
+  /// Create a new telegram object with an owned topic.  The topic is
+  /// validated, and then stored without being copied.
+  ///
+  /// ```
+  /// use blather::Telegram;
+  /// fn main() {
+  ///   let topic = format!("Job{}", 42);
+  ///   let tg = Telegram::new_topic_owned(topic).unwrap();
+  ///   assert_eq!(tg.get_topic(), Some("Job42"));
+  /// }
+  /// ```
+  pub fn new_topic_owned(topic: String) -> Result<Self, Error> {
+    validate_topic(&topic)?;
+    Ok(Telegram::new_topic_unchecked(topic))
+  }
+
+
+  /// Create a new telegram object with a topic, without validating the
+  /// topic.
+  ///
+  /// # Notes
+  /// - This is not memory-unsafe, but it is logically unsafe: it can be used
+  ///   to create a telegram which can not be parsed by a receiving peer.  It
+  ///   exists for hot paths where topics come from a trusted source, and
+  ///   should not be used on untrusted input.  Use
+  ///   [`new_topic()`](Self::new_topic) in the general case.
+  pub fn new_topic_unchecked(topic: String) -> Self {
+    Telegram {
+      topic: Some(topic),
+      ..Default::default()
+    }
+  }
+
+
+  /// Create a new telegram with the same topic as this one, but without any
+  /// parameters.
+  ///
+  /// Returns `Error::BadState` if this telegram does not have a topic.
+  ///
+  /// ```
+  /// use blather::Telegram;
+  /// fn main() {
+  ///   let mut req = Telegram::new_topic("Query").unwrap();
+  ///   req.add_param("Id", 42).unwrap();
+  ///   let resp = req.clone_topic().unwrap();
+  ///   assert_eq!(resp.get_topic(), Some("Query"));
+  ///   assert_eq!(resp.num_params(), 0);
+  /// }
+  /// ```
+  pub fn clone_topic(&self) -> Result<Telegram, Error> {
+    match self.topic {
+      Some(ref topic) => Ok(Telegram::new_topic_unchecked(topic.clone())),
+      None => Err(Error::BadState("Missing Telegram topic".to_string()))
+    }
+  }
+
+
   /// Clear topic and internal parameters buffer.
   ///
   /// ```
@@ -94,6 +154,16 @@ impl Telegram {
   }
 
 
+  /// Return the keys of the telegram's parameters, sorted.
+  ///
+  /// # Notes
+  /// - This is a thin wrapper around
+  ///   [`Params::param_names()`](crate::Params::param_names).
+  pub fn param_names(&self) -> Vec<&str> {
+    self.params.param_names()
+  }
+
+
   /// Get a reference to the internal parameters object.
   pub fn get_params(&self) -> &Params {
     &self.params
@@ -122,7 +192,7 @@ impl Telegram {
   /// Note: The inner representation of the Params object may change in the
   /// future.
   pub fn get_params_inner(&self) -> &HashMap<String, String> {
-    &self.params.get_inner()
+    self.params.get_inner()
   }
 
 
@@ -148,6 +218,32 @@ impl Telegram {
   }
 
 
+  /// Set an owned topic for telegram.  The topic is validated, and then
+  /// stored without being copied.
+  ///
+  /// Overwrites current topic is one has already been set.
+  pub fn set_topic_owned(&mut self, topic: String) -> Result<(), Error> {
+    validate_topic(&topic)?;
+    self.topic = Some(topic);
+    Ok(())
+  }
+
+
+  /// Set topic for telegram, without validating the topic.
+  ///
+  /// Overwrites current topic is one has already been set.
+  ///
+  /// # Notes
+  /// - This is not memory-unsafe, but it is logically unsafe: it can be used
+  ///   to create a telegram which can not be parsed by a receiving peer.  It
+  ///   exists for hot paths where topics come from a trusted source, and
+  ///   should not be used on untrusted input.  Use
+  ///   [`set_topic()`](Self::set_topic) in the general case.
+  pub fn set_topic_unchecked(&mut self, topic: String) {
+    self.topic = Some(topic);
+  }
+
+
   /// Get a reference to the topic string, or None if topic is not been set.
   ///
   /// # Examples
@@ -170,6 +266,67 @@ impl Telegram {
   }
 
 
+  /// Returns `true` if a topic has been set.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Telegram;
+  /// fn main() {
+  ///   assert!(Telegram::new_topic("Hello").unwrap().has_topic());
+  ///   assert!(!Telegram::new().has_topic());
+  /// }
+  /// ```
+  pub fn has_topic(&self) -> bool {
+    self.topic.is_some()
+  }
+
+
+  /// Check whether the telegram's topic matches a pattern.
+  ///
+  /// Both the topic and the pattern are split into segments at `/`
+  /// characters.  A pattern segment consisting of `*` matches any single
+  /// topic segment, and a final pattern segment consisting of `**` matches
+  /// any number (including zero) of remaining topic segments.  All other
+  /// pattern segments must match the topic segments exactly.
+  ///
+  /// Returns `false` if the telegram doesn't have a topic.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Telegram;
+  /// fn main() {
+  ///   let tg = Telegram::new_topic("AddUser").unwrap();
+  ///   assert!(tg.topic_matches("AddUser"));
+  ///   assert!(tg.topic_matches("*"));
+  ///   assert!(!tg.topic_matches("DelUser"));
+  /// }
+  /// ```
+  ///
+  /// # Notes
+  /// - Validated topics can not contain `/`, so they always consist of a
+  ///   single segment.
+  pub fn topic_matches(&self, pattern: &str) -> bool {
+    let topic = match self.topic {
+      Some(ref topic) => topic,
+      None => return false
+    };
+
+    let mut segs = topic.split('/');
+    let mut pats = pattern.split('/').peekable();
+    while let Some(pat) = pats.next() {
+      if pat == "**" && pats.peek().is_none() {
+        return true;
+      }
+      match segs.next() {
+        Some(seg) if pat == "*" || pat == seg => {}
+        _ => return false
+      }
+    }
+
+    segs.next().is_none()
+  }
+
+
   /// Add a parameter to the telegram.
   ///
   /// The `key` and `value` parameters are generic over the trait `ToString`,
@@ -197,6 +354,34 @@ impl Telegram {
   }
 
 
+  /// Add a parameter to the telegram, unless a parameter with the same key
+  /// already exists.  Returns `Ok(true)` if the parameter was added and
+  /// `Ok(false)` if the key already existed.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Telegram;
+  /// fn main() {
+  ///   let mut tg = Telegram::new_topic("Connect").unwrap();
+  ///   tg.add_param("Port", 8080).unwrap();
+  ///   assert_eq!(tg.set_param_if_absent("Port", 80), Ok(false));
+  ///   assert_eq!(tg.set_param_if_absent("Host", "localhost"), Ok(true));
+  ///   assert_eq!(tg.get_str("Port"), Some("8080"));
+  /// }
+  /// ```
+  ///
+  /// # Notes
+  /// - This is a thin wrapper around
+  ///   [`Params::set_param_if_absent()`](crate::Params::set_param_if_absent).
+  pub fn set_param_if_absent<T: ToString, U: ToString>(
+    &mut self,
+    key: T,
+    value: U
+  ) -> Result<bool, Error> {
+    self.params.set_param_if_absent(key, value)
+  }
+
+
   /// Add a string parameter to the telegram.
   ///
   /// # Notes
@@ -279,6 +464,21 @@ impl Telegram {
   }
 
 
+  /// Get a parameter.  Return a value computed by `f` if the parameter does
+  /// not exist.
+  ///
+  /// # Notes
+  /// - This is a thin wrapper around
+  ///   [`Params::get_param_or()`](crate::Params::get_param_or).
+  pub fn get_param_or<T: FromStr, F: FnOnce() -> T>(
+    &self,
+    key: &str,
+    f: F
+  ) -> Result<T, Error> {
+    self.params.get_param_or(key, f)
+  }
+
+
   /// Get a string representation of a parameter.  Return `None` is parameter
   /// does not exist.
   ///
@@ -378,6 +578,20 @@ impl Telegram {
     self.params.get_strvec(key)
   }
 
+  /// Parse the value of a key as a comma-separated list of strings and return
+  /// it as a `Vec<String>`.  Returns `def` if the key does not exist.
+  ///
+  /// # Notes
+  /// - This is a thin wrapper around
+  ///   [`Params::get_strvec_def()`](crate::Params::get_strvec_def).
+  pub fn get_strvec_def(
+    &self,
+    key: &str,
+    def: Vec<String>
+  ) -> Result<Vec<String>, Error> {
+    self.params.get_strvec_def(key, def)
+  }
+
   /// Parse the value of a key as a comma-separated list of strings and return
   /// it as a `HashSet<String>`.  Only non-empty entries are returned.
   ///
@@ -388,6 +602,188 @@ impl Telegram {
     self.params.get_hashset(key)
   }
 
+  /// Parse the value of a key as a comma-separated list of strings and return
+  /// it as a `HashSet<String>`.  Returns `def` if the key does not exist.
+  ///
+  /// # Notes
+  /// - This is a thin wrapper around
+  ///   [`Params::get_hashset_def()`](crate::Params::get_hashset_def).
+  pub fn get_hashset_def(
+    &self,
+    key: &str,
+    def: HashSet<String>
+  ) -> Result<HashSet<String>, Error> {
+    self.params.get_hashset_def(key, def)
+  }
+
+
+  /// Split the value of a key into the parts separated by `sep`, returning
+  /// borrowed slices of the value.
+  ///
+  /// This is intended for reading parameters which were received multiple
+  /// times and joined by the decoder (see
+  /// [`Codec::set_repeated_key_join()`](crate::Codec::set_repeated_key_join)).
+  /// Unlike [`get_strvec()`](Self::get_strvec), empty parts are retained, so
+  /// each part corresponds to one received value.  Returns an empty vector if
+  /// the key does not exist.
+  ///
+  /// ```
+  /// use blather::Telegram;
+  /// fn main() {
+  ///   let mut tg = Telegram::new_topic("Tagged").unwrap();
+  ///   tg.add_param("Tag", "red|blue").unwrap();
+  ///   assert_eq!(tg.get_param_multi("Tag", '|'), vec!["red", "blue"]);
+  ///   assert!(tg.get_param_multi("Missing", '|').is_empty());
+  /// }
+  /// ```
+  pub fn get_param_multi(&self, key: &str, sep: char) -> Vec<&str> {
+    match self.params.get_str(key) {
+      Some(v) => v.split(sep).collect(),
+      None => Vec::new()
+    }
+  }
+
+
+  /// Merge the parameters of another telegram, with the same topic, into this
+  /// telegram.  Parameters in `other` overwrite parameters with the same key
+  /// in `self`.
+  ///
+  /// Returns `Error::BadState` if the topics differ or if either telegram
+  /// lacks a topic.
+  ///
+  /// ```
+  /// use blather::Telegram;
+  /// fn main() {
+  ///   let mut tg = Telegram::new_topic("User").unwrap();
+  ///   tg.add_param("Name", "Frank").unwrap();
+  ///   tg.add_param("Age", "41").unwrap();
+  ///
+  ///   let mut update = Telegram::new_topic("User").unwrap();
+  ///   update.add_param("Age", "42").unwrap();
+  ///
+  ///   tg.merge_params(&update).unwrap();
+  ///   assert_eq!(tg.get_str("Name"), Some("Frank"));
+  ///   assert_eq!(tg.get_str("Age"), Some("42"));
+  /// }
+  /// ```
+  pub fn merge_params(&mut self, other: &Telegram) -> Result<(), Error> {
+    match (&self.topic, &other.topic) {
+      (Some(a), Some(b)) if a == b => {}
+      (Some(_), Some(_)) => {
+        return Err(Error::BadState("Telegram topics differ".to_string()));
+      }
+      _ => {
+        return Err(Error::BadState("Missing Telegram topic".to_string()));
+      }
+    }
+
+    for (key, value) in other.get_params_inner() {
+      self.params.add_param(key, value)?;
+    }
+
+    Ok(())
+  }
+
+
+  /// Return the keys of all parameters which are not in the `allowed` set.
+  ///
+  /// This can be used to detect unexpected parameters after a telegram has
+  /// been decoded.  The returned keys are sorted.
+  ///
+  /// ```
+  /// use std::collections::HashSet;
+  /// use blather::Telegram;
+  /// fn main() {
+  ///   let mut tg = Telegram::new_topic("AddUser").unwrap();
+  ///   tg.add_param("Name", "Frank").unwrap();
+  ///   tg.add_param("Shoe", "42").unwrap();
+  ///
+  ///   let allowed: HashSet<&str> = ["Name", "Age"].iter().copied().collect();
+  ///   assert_eq!(tg.unknown_params(&allowed), vec!["Shoe"]);
+  /// }
+  /// ```
+  pub fn unknown_params(&self, allowed: &HashSet<&str>) -> Vec<&str> {
+    let mut unknown: Vec<&str> = self
+      .get_params_inner()
+      .keys()
+      .map(|k| k.as_str())
+      .filter(|k| !allowed.contains(k))
+      .collect();
+    unknown.sort_unstable();
+    unknown
+  }
+
+
+  /// Returns `true` if this telegram and `other` have the same topic and the
+  /// same parameters, not counting the parameters whose keys are listed in
+  /// `ignore`.  Ignored keys may be missing on either side.
+  ///
+  /// ```
+  /// use blather::Telegram;
+  /// fn main() {
+  ///   let mut a = Telegram::new_topic("Status").unwrap();
+  ///   a.add_param("State", "idle").unwrap();
+  ///   a.add_param("Time", 1000).unwrap();
+  ///
+  ///   let mut b = a.clone();
+  ///   b.add_param("Time", 2000).unwrap();
+  ///
+  ///   assert!(a.eq_ignoring(&b, &["Time"]));
+  ///   assert!(!a.eq_ignoring(&b, &[]));
+  /// }
+  /// ```
+  pub fn eq_ignoring(&self, other: &Telegram, ignore: &[&str]) -> bool {
+    if self.topic != other.topic {
+      return false;
+    }
+
+    let a = self.get_params_inner();
+    let b = other.get_params_inner();
+    let count = |hm: &HashMap<String, String>| {
+      hm.keys().filter(|k| !ignore.contains(&k.as_str())).count()
+    };
+    if count(a) != count(b) {
+      return false;
+    }
+    a.iter()
+      .filter(|(k, _)| !ignore.contains(&k.as_str()))
+      .all(|(k, v)| b.get(k) == Some(v))
+  }
+
+
+  /// Get the number of bytes this Telegram occupies on the wire.
+  ///
+  /// The size consists of:
+  /// - The topic line, including its newline character.
+  /// - One line per parameter, consisting of the key, a space character, the
+  ///   value and a newline character.
+  /// - The terminating empty line (a single newline character).
+  ///
+  /// Returns `Error::SerializeError` if the Telegram does not have a topic,
+  /// since such a Telegram can not be transmitted.
+  ///
+  /// # Notes
+  /// - The size does not include any additional framing which may be added
+  ///   by the [`Codec`](crate::codec::Codec), such as length prefixes,
+  ///   integrity trailers or alternate block terminators.
+  pub fn wire_size(&self) -> Result<usize, Error> {
+    if self.topic.is_none() {
+      return Err(Error::SerializeError("Missing Telegram topic".to_string()));
+    }
+    Ok(self.calc_buf_size())
+  }
+
+
+  /// Return an estimate of the number of bytes of heap memory used by the
+  /// telegram, including its topic.
+  ///
+  /// See [`Params::approx_heap_size()`](crate::Params::approx_heap_size) for
+  /// details.
+  pub fn approx_heap_size(&self) -> usize {
+    let topic = self.topic.as_ref().map_or(0, |t| t.capacity());
+    topic + self.params.approx_heap_size()
+  }
+
 
   /// Calculate the size of a serialized version of this Telegram object.
   /// If no topic has been set it is simply ignored.  In the future this might
@@ -411,39 +807,151 @@ impl Telegram {
   /// Serialize `Telegram` into a vector of bytes for transmission.
   pub fn serialize(&self) -> Result<Vec<u8>, Error> {
     let mut buf = Vec::new();
+    self.serialize_into(&mut buf)?;
+    Ok(buf)
+  }
 
-    if let Some(ref h) = self.topic {
-      // Copy topic
-      let b = h.as_bytes();
-      for a in b {
-        buf.push(*a);
-      }
-      buf.push(b'\n');
-    } else {
-      return Err(Error::BadFormat("Missing heading".to_string()));
-    }
 
-    for (key, value) in self.get_params_inner() {
-      let k = key.as_bytes();
-      let v = value.as_bytes();
-      for a in k {
-        buf.push(*a);
-      }
+  /// Serialize `Telegram` and append the result to a caller-owned buffer.
+  ///
+  /// # Notes
+  /// - Nothing is written to the buffer if the Telegram does not have a
+  ///   topic.
+  pub fn serialize_into(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+    self.serialize_lines(self.entries(), buf, LineEnding::Lf)
+  }
+
+
+  /// Serialize `Telegram` into a vector of bytes, terminating each line
+  /// using the requested [`LineEnding`].
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::{LineEnding, Telegram};
+  /// fn main() {
+  ///   let mut tg = Telegram::new_topic("Hello").unwrap();
+  ///   tg.add_param("Name", "Frank").unwrap();
+  ///   let buf = tg.serialize_with(LineEnding::CrLf).unwrap();
+  ///   assert_eq!(buf, b"Hello\r\nName Frank\r\n\r\n");
+  /// }
+  /// ```
+  pub fn serialize_with(&self, ending: LineEnding) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    self.serialize_lines(self.entries(), &mut buf, ending)?;
+    Ok(buf)
+  }
+
+
+  /// Return the key/value pairs of the Telegram, sorted by key.
+  ///
+  /// This can be used to process the parameters in a deterministic order.
+  ///
+  /// # Examples
+  /// ```
+  /// use blather::Telegram;
+  /// fn main() {
+  ///   let mut tg = Telegram::new_topic("User").unwrap();
+  ///   tg.add_param("Name", "Frank").unwrap();
+  ///   tg.add_param("Age", 42).unwrap();
+  ///   assert_eq!(
+  ///     tg.entries_sorted(),
+  ///     vec![("Age", "42"), ("Name", "Frank")]
+  ///   );
+  /// }
+  /// ```
+  pub fn entries_sorted(&self) -> Vec<(&str, &str)> {
+    let mut entries: Vec<(&str, &str)> = self.entries().collect();
+    entries.sort_unstable();
+    entries
+  }
+
+
+  /// Serialize `Telegram` into a vector of bytes, with the parameters
+  /// sorted by key.
+  ///
+  /// Unlike [`serialize()`](Self::serialize), the output only depends on
+  /// the telegram's contents, which makes it suitable for comparisons and
+  /// golden tests.
+  pub fn serialize_sorted(&self) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    self.serialize_lines(self.entries_sorted(), &mut buf, LineEnding::Lf)?;
+    Ok(buf)
+  }
+
+
+  /// Return an iterator over the key/value pairs, in arbitrary order.
+  fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+    self
+      .get_params_inner()
+      .iter()
+      .map(|(k, v)| (k.as_str(), v.as_str()))
+  }
+
+
+  /// Append the topic line, the key/value lines in `entries` and the
+  /// terminating empty line to `buf`, using the line ending `ending`.
+  fn serialize_lines<'a, I>(
+    &self,
+    entries: I,
+    buf: &mut Vec<u8>,
+    ending: LineEnding
+  ) -> Result<(), Error>
+  where
+    I: IntoIterator<Item = (&'a str, &'a str)>
+  {
+    let h = match self.topic {
+      Some(ref h) => h,
+      None => return Err(Error::BadFormat("Missing heading".to_string()))
+    };
+
+    let eol = ending.as_bytes();
+    buf.reserve(
+      self.calc_buf_size() + (eol.len() - 1) * (self.num_params() + 2)
+    );
+
+    // Copy topic
+    buf.extend_from_slice(h.as_bytes());
+    buf.extend_from_slice(eol);
+
+    for (key, value) in entries {
+      buf.extend_from_slice(key.as_bytes());
       buf.push(b' ');
-      for a in v {
-        buf.push(*a);
-      }
-      buf.push(b'\n');
+      buf.extend_from_slice(value.as_bytes());
+      buf.extend_from_slice(eol);
     }
 
-    buf.push(b'\n');
+    buf.extend_from_slice(eol);
 
-    Ok(buf)
+    Ok(())
+  }
+
+
+  /// Encode the Telegram into an immutable buffer, in the same format as
+  /// [`encoder_write()`](Self::encoder_write).
+  ///
+  /// Returns `Error::SerializeError` if the Telegram does not have a topic.
+  pub fn to_bytes(&self) -> Result<Bytes, Error> {
+    let mut buf = BytesMut::with_capacity(self.calc_buf_size());
+    self.encoder_write(&mut buf)?;
+    Ok(buf.freeze())
   }
 
 
   /// Write the Telegram to a BytesMut buffer.
   pub fn encoder_write(&self, buf: &mut BytesMut) -> Result<(), Error> {
+    self.encoder_write_lines(buf)?;
+    buf.put_u8(b'\n');
+
+    Ok(())
+  }
+
+
+  /// Write the topic and parameter lines of the Telegram to a BytesMut
+  /// buffer, but not the terminating empty line.
+  pub(crate) fn encoder_write_lines(
+    &self,
+    buf: &mut BytesMut
+  ) -> Result<(), Error> {
     if self.topic.is_none() {
       return Err(Error::SerializeError("Missing Telegram topic".to_string()));
     }
@@ -466,16 +974,69 @@ impl Telegram {
       buf.put(value.as_bytes());
       buf.put_u8(b'\n');
     }
-    buf.put_u8(b'\n');
 
     Ok(())
   }
 
 
+  /// Retain only the parameters for which the predicate `f`, called with
+  /// each key and value, returns `true`.
+  ///
+  /// # Notes
+  /// - This is a thin wrapper around
+  ///   [`Params::retain()`](crate::Params::retain).
+  pub fn retain_params<F: FnMut(&str, &str) -> bool>(&mut self, f: F) {
+    self.params.retain(f)
+  }
+
+
   /// Consume the Telegram buffer and return the internal parameters object.
   pub fn into_params(self) -> Params {
     self.params
   }
+
+  /// Consume the Telegram buffer and return both the topic and the internal
+  /// parameters object.
+  pub fn into_topic_and_params(self) -> (Option<String>, Params) {
+    (self.topic, self.params)
+  }
+}
+
+/// Construct a value from the parameters of a [`Telegram`].
+///
+/// With the `derive` feature enabled this can be derived for structs with
+/// named fields.  Each field is read from the parameter of the same name and
+/// parsed using its `FromStr` implementation:
+/// - `Option<T>` fields are `None` if the parameter does not exist.
+/// - Fields marked `#[blather(default)]` use their type's `Default` value if
+///   the parameter does not exist.
+/// - `#[blather(rename = "Key")]` reads a field from the parameter `Key`.
+///
+/// All other fields are required, and cause an `Error::KeyNotFound` if
+/// missing.
+///
+/// ```
+/// use blather::{Error, FromTelegram, Telegram};
+///
+/// struct Job {
+///   id: u32
+/// }
+///
+/// impl FromTelegram for Job {
+///   fn from_telegram(tg: &Telegram) -> Result<Self, Error> {
+///     Ok(Job {
+///       id: tg.get_param("id")?
+///     })
+///   }
+/// }
+///
+/// let mut tg = Telegram::new_topic("Job").unwrap();
+/// tg.add_param("id", 42).unwrap();
+/// assert_eq!(Job::from_telegram(&tg).unwrap().id, 42);
+/// ```
+pub trait FromTelegram: Sized {
+  /// Construct a value from the parameters of `tg`.
+  fn from_telegram(tg: &Telegram) -> Result<Self, Error>;
 }
 
 impl From<String> for Telegram {
@@ -496,6 +1057,10 @@ impl From<Params> for Telegram {
   }
 }
 
+/// Create a `Telegram`, without a topic, from a `HashMap`, without validating
+/// its keys.
+///
+/// See `From<HashMap>` for [`Params`].
 impl From<HashMap<String, String>> for Telegram {
   fn from(hm: HashMap<String, String>) -> Self {
     Telegram {
@@ -505,11 +1070,103 @@ impl From<HashMap<String, String>> for Telegram {
   }
 }
 
+/// Parse the text form of a single telegram.
+///
+/// The first line is the topic, and each following line is a key/value pair
+/// separated by the first space character.  The terminating empty line is
+/// optional.  Lines without a space character are ignored, just like the
+/// [`Codec`](crate::codec::Codec) does.
+///
+/// ```
+/// use blather::Telegram;
+///
+/// fn main() {
+///   let tg: Telegram = "Hello\nName Frank\n".parse().unwrap();
+///   assert_eq!(tg.get_topic(), Some("Hello"));
+///   assert_eq!(tg.get_str("Name"), Some("Frank"));
+/// }
+/// ```
+impl FromStr for Telegram {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let lines = lines::text_block(s)?;
+    let mut it = lines.into_iter();
+
+    let mut tg = match it.next() {
+      Some(topic) => Telegram::new_topic(topic)?,
+      None => return Err(Error::BadFormat("Missing topic".to_string()))
+    };
+    for line in it {
+      if let Some((k, v)) = lines::split_kv(line) {
+        tg.add_param(k, v)?;
+      }
+    }
+    Ok(tg)
+  }
+}
+
+impl AsRef<Params> for Telegram {
+  fn as_ref(&self) -> &Params {
+    &self.params
+  }
+}
+
+/// Make all [`Params`] methods callable directly on a `Telegram`.
+///
+/// Method calls resolve as follows:
+/// - Methods implemented by `Telegram` take precedence over `Params` methods
+///   with the same name.  Those which describe the entire telegram take the
+///   topic into account: [`Telegram::clear()`] also clears the topic, and
+///   [`serialize()`](Telegram::serialize),
+///   [`to_bytes()`](Telegram::to_bytes),
+///   [`encoder_write()`](Telegram::encoder_write),
+///   [`calc_buf_size()`](Telegram::calc_buf_size) and
+///   [`approx_heap_size()`](Telegram::approx_heap_size) include the topic
+///   line.  Note that [`Telegram::wire_size()`] returns a `Result`, since a
+///   telegram without a topic can not be transmitted, while
+///   [`Params::wire_size()`] returns a `usize`.
+/// - All other methods are `Params` methods, which only operate on the
+///   parameters and ignore the topic.  In particular
+///   [`len()`](Params::len) and [`is_empty()`](Params::is_empty) count
+///   parameters only, so a telegram which only has a topic is empty; use
+///   [`Telegram::has_topic()`] to check for a topic.
+///
+/// To call a `Params` method which is shadowed by a `Telegram` method, go
+/// through [`Telegram::get_params()`], `AsRef<Params>` or an explicit
+/// dereference.
+///
+/// ```
+/// use blather::Telegram;
+/// fn main() {
+///   let mut tg = Telegram::new_topic("Hello").unwrap();
+///   assert!(tg.is_empty());
+///   tg.add_param("Name", "Frank").unwrap();
+///   assert_eq!(tg.len(), 1);
+///   assert_eq!(tg.keys().collect::<Vec<_>>(), vec!["Name"]);
+///   assert_eq!(tg.wire_size().unwrap(), 18);
+///   assert_eq!((*tg).wire_size(), 12);
+/// }
+/// ```
+impl Deref for Telegram {
+  type Target = Params;
+
+  fn deref(&self) -> &Params {
+    &self.params
+  }
+}
+
+impl DerefMut for Telegram {
+  fn deref_mut(&mut self) -> &mut Params {
+    &mut self.params
+  }
+}
+
 impl fmt::Display for Telegram {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     let topic: &str = match &self.topic {
       Some(s) => s.as_ref(),
-      None => &"<None>"
+      None => "<None>"
     };
 
     write!(f, "{}:{}", topic, self.params)
@@ -9,6 +9,22 @@ fn is_topic_char(c: char) -> bool {
 }
 
 /// Make sure that topic string is valid.
+///
+/// A topic must begin with an alphabetic character, followed by
+/// alphanumeric, `_` or `-` characters.  Returns `Error::BadFormat` if the
+/// topic is invalid.
+///
+/// ```
+/// use blather::{validate::validate_topic, Telegram};
+///
+/// let input = "Add User";
+/// if validate_topic(input).is_err() {
+///   // Give early feedback, before any telegram is constructed.
+///   println!("'{}' is not a valid topic", input);
+/// }
+/// assert!(validate_topic("AddUser").is_ok());
+/// assert!(Telegram::new_topic("AddUser").is_ok());
+/// ```
 pub fn validate_topic(topic: &str) -> Result<(), Error> {
   let mut chars = topic.chars();
   match chars.next() {
@@ -34,6 +50,17 @@ fn is_key_char(c: char) -> bool {
 }
 
 /// Make sure that a parameter key is valid.
+///
+/// A key must consist of alphanumeric or ASCII punctuation characters.
+/// Returns `Error::BadFormat` if the key is invalid.
+///
+/// ```
+/// use blather::validate::validate_param_key;
+///
+/// assert!(validate_param_key("user.name").is_ok());
+/// assert!(validate_param_key("user name").is_err());
+/// assert!(validate_param_key("").is_err());
+/// ```
 pub fn validate_param_key(key: &str) -> Result<(), Error> {
   let mut chars = key.chars();
   match chars.next() {
@@ -52,6 +79,53 @@ pub fn validate_param_key(key: &str) -> Result<(), Error> {
 }
 
 
+/// Policy used to restrict which characters are allowed in parameter keys.
+///
+/// The default policy accepts the same keys as [`Params::new()`], i.e.
+/// alphanumeric and ASCII punctuation characters.  A policy can only restrict
+/// the set of accepted characters further.
+///
+/// ```
+/// use blather::{KeyPolicy, Params};
+///
+/// fn main() {
+///   let mut params = Params::new_with_policy(KeyPolicy::new().forbid(".="));
+///   assert!(params.add_str("name", "frank").is_ok());
+///   assert!(params.add_str("user.name", "frank").is_err());
+/// }
+/// ```
+///
+/// [`Params::new()`]: crate::Params::new
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyPolicy {
+  forbidden: Vec<char>
+}
+
+impl KeyPolicy {
+  /// Create a new policy which accepts all valid parameter keys.
+  pub fn new() -> Self {
+    KeyPolicy::default()
+  }
+
+  /// Forbid all the characters in `chars` from appearing in keys.
+  pub fn forbid(mut self, chars: &str) -> Self {
+    self.forbidden.extend(chars.chars());
+    self
+  }
+
+  /// Make sure that a parameter key is valid according to this policy.
+  pub fn validate(&self, key: &str) -> Result<(), Error> {
+    validate_param_key(key)?;
+    if let Some(c) = key.chars().find(|c| self.forbidden.contains(c)) {
+      return Err(Error::BadFormat(format!(
+        "Key character '{}' forbidden by policy",
+        c
+      )));
+    }
+    Ok(())
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::validate_topic;
@@ -0,0 +1,1307 @@
+#![cfg(feature = "codec")]
+
+use std::cell::Cell;
+use std::io::Write;
+use std::sync::{mpsc, Arc, Mutex};
+
+use bytes::BytesMut;
+
+use tokio_util::codec::{Decoder, Encoder};
+
+use blather::codec::{CodecStats, Framing, Mode, Terminator};
+use blather::{codec, Codec, Error, KVLines, Params, Telegram};
+
+#[test]
+fn yield_after() {
+  let mut codec = Codec::new();
+  codec.set_yield_after(2);
+
+  let mut buf = BytesMut::from(&b"one\n\ntwo\n\nthree\n\n"[..]);
+
+  for topic in &["one", "two"] {
+    match codec.decode(&mut buf).unwrap() {
+      Some(codec::Input::Telegram(tg)) => {
+        assert_eq!(tg.get_topic(), Some(*topic));
+      }
+      _ => panic!("Not a Telegram")
+    }
+  }
+
+  // Yield boundary, even though a complete frame is buffered
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("three"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+
+fn integrity_encode() -> BytesMut {
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  tg.add_param("foo", "bar").unwrap();
+  tg.add_param("moo", "cow").unwrap();
+
+  let mut codec = Codec::new();
+  codec.set_integrity(true);
+
+  let mut buf = BytesMut::new();
+  codec.encode(&tg, &mut buf).unwrap();
+  buf
+}
+
+#[test]
+fn integrity_roundtrip() {
+  let mut buf = integrity_encode();
+
+  let mut codec = Codec::new();
+  codec.set_integrity(true);
+
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+      assert_eq!(tg.num_params(), 2);
+      assert_eq!(tg.get_str("foo"), Some("bar"));
+      assert_eq!(tg.get_str("moo"), Some("cow"));
+      assert!(!tg.have_param("__crc"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+#[test]
+fn integrity_corrupted() {
+  let mut buf = integrity_encode();
+
+  // Flip a byte in the topic
+  buf[1] = b'a';
+
+  let mut codec = Codec::new();
+  codec.set_integrity(true);
+
+  match codec.decode(&mut buf) {
+    Err(Error::BadFormat(s)) => assert_eq!(s, "Integrity check failed"),
+    _ => panic!("Expected a BadFormat error")
+  }
+}
+
+#[test]
+fn integrity_missing() {
+  let mut buf = BytesMut::from(&b"hello\nfoo bar\n\n"[..]);
+
+  let mut codec = Codec::new();
+  codec.set_integrity(true);
+
+  match codec.decode(&mut buf) {
+    Err(Error::BadFormat(s)) => assert_eq!(s, "Missing integrity trailer"),
+    _ => panic!("Expected a BadFormat error")
+  }
+}
+
+
+#[test]
+fn trim_values() {
+  let mut codec = Codec::new();
+  codec.set_trim_values(true);
+
+  let mut buf = BytesMut::from(&b"hello\nAge  42   \nName Frank\n\n"[..]);
+
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_str("Age"), Some("42"));
+      assert_eq!(tg.get_param::<u8>("Age").unwrap(), 42);
+      assert_eq!(tg.get_str("Name"), Some("Frank"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+
+#[test]
+fn length_prefixed_roundtrip() {
+  let mut codec = Codec::new();
+  codec.set_framing(Framing::LengthPrefixed);
+
+  let mut buf = BytesMut::new();
+  for topic in &["one", "two"] {
+    let mut tg = Telegram::new_topic(topic).unwrap();
+    tg.add_param("foo", "bar").unwrap();
+    codec.encode(&tg, &mut buf).unwrap();
+  }
+
+  // "one\nfoo bar\n\n"
+  assert_eq!(&buf[..4], &[0, 0, 0, 13]);
+
+  for topic in &["one", "two"] {
+    match codec.decode(&mut buf).unwrap() {
+      Some(codec::Input::Telegram(tg)) => {
+        assert_eq!(tg.get_topic(), Some(*topic));
+        assert_eq!(tg.get_str("foo"), Some("bar"));
+      }
+      _ => panic!("Not a Telegram")
+    }
+  }
+  assert!(buf.is_empty());
+}
+
+#[test]
+fn length_prefixed_unterminated() {
+  let mut codec = Codec::new();
+  codec.set_framing(Framing::LengthPrefixed);
+
+  let mut buf = BytesMut::from(&b"\0\0\0\x0dhello\nfoo bar"[..]);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+      assert_eq!(tg.get_str("foo"), Some("bar"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+#[test]
+fn length_prefixed_incomplete() {
+  let mut codec = Codec::new();
+  codec.set_framing(Framing::LengthPrefixed);
+
+  // Incomplete length prefix
+  let mut buf = BytesMut::from(&b"\0\0"[..]);
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+  assert_eq!(buf.len(), 2);
+
+  // Complete length prefix, incomplete telegram
+  buf.extend_from_slice(b"\0\x0bhello\n");
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+
+  buf.extend_from_slice(b"foo\n\n");
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+#[test]
+fn length_prefixed_too_long() {
+  let mut codec = Codec::new();
+  codec.set_framing(Framing::LengthPrefixed);
+
+  let mut buf = BytesMut::from(&b"\xff\xff\xff\xffhello\n"[..]);
+  let cap = buf.capacity();
+  match codec.decode(&mut buf) {
+    Err(Error::LimitExceeded(_)) => {}
+    _ => panic!("Expected a LimitExceeded error")
+  }
+  assert_eq!(buf.capacity(), cap);
+
+  codec.set_max_frame_len(8);
+  let mut buf = BytesMut::from(&b"\0\0\0\x09hello\n\n"[..]);
+  assert!(matches!(codec.decode(&mut buf), Err(Error::LimitExceeded(_))));
+}
+
+#[test]
+fn length_prefixed_error_discards() {
+  let mut codec = Codec::new();
+  codec.set_framing(Framing::LengthPrefixed);
+
+  // Invalid UTF-8 after a parameter line
+  let mut buf = BytesMut::from(&b"\0\0\0\x10hello\nfoo bar\n\xff\n"[..]);
+  assert!(codec.decode(&mut buf).is_err());
+
+  // Data after the terminator
+  buf.extend_from_slice(b"\0\0\0\x11hello\nmoo cow\n\nx\n");
+  assert!(matches!(codec.decode(&mut buf), Err(Error::BadFormat(_))));
+
+  buf.extend_from_slice(b"\0\0\0\x0bother\nx y\n\n");
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("other"));
+      assert_eq!(tg.num_params(), 1);
+      assert_eq!(tg.get_str("x"), Some("y"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+  assert!(buf.is_empty());
+}
+
+#[test]
+fn length_prefixed_leading_blanks() {
+  let mut codec = Codec::new();
+  codec.set_framing(Framing::LengthPrefixed);
+  codec.set_skip_leading_blanks(true);
+
+  let mut buf = BytesMut::from(&b"\0\0\0\x11\n\nhello\nfoo bar\n\n"[..]);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+      assert_eq!(tg.get_str("foo"), Some("bar"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+  assert!(buf.is_empty());
+}
+
+
+#[test]
+fn comments() {
+  let mut codec = Codec::new();
+  codec.set_allow_comments(true);
+  codec.expect_params();
+
+  let mut buf =
+    BytesMut::from(&b"# leading\nfoo bar\n#foo baz\nmoo cow\n# end\n\n"[..]);
+
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Params(params)) => {
+      assert_eq!(params.len(), 2);
+      assert_eq!(params.get_str("foo"), Some("bar"));
+      assert_eq!(params.get_str("moo"), Some("cow"));
+      assert!(!params.have("#"));
+      assert!(!params.have("#foo"));
+    }
+    _ => panic!("Not a Params")
+  }
+}
+
+
+#[test]
+fn mode() {
+  let mut codec = Codec::new();
+  assert_eq!(codec.mode(), Mode::Telegram);
+  assert!(codec.is_expecting_telegram());
+
+  codec.expect_bytes(4).unwrap();
+  assert_eq!(codec.mode(), Mode::Bytes);
+  assert!(!codec.is_expecting_telegram());
+
+  let mut buf = BytesMut::from(&b"12"[..]);
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+  assert_eq!(codec.mode(), Mode::Bytes);
+
+  buf.extend_from_slice(b"34");
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Bytes(b)) => assert_eq!(&b[..], b"1234"),
+    _ => panic!("Not a Bytes")
+  }
+  assert_eq!(codec.mode(), Mode::Telegram);
+  assert!(codec.is_expecting_telegram());
+}
+
+
+#[test]
+fn reject_duplicate_keys() {
+  let data = &b"hello\nfoo bar\nfoo baz\n\n"[..];
+
+  // Disabled by default; later value wins
+  let mut codec = Codec::new();
+  let mut buf = BytesMut::from(data);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_str("foo"), Some("baz"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+
+  let mut codec = Codec::new();
+  codec.set_reject_duplicate_keys(true);
+  let mut buf = BytesMut::from(data);
+  match codec.decode(&mut buf) {
+    Err(Error::BadFormat(msg)) => assert!(msg.contains("foo")),
+    _ => panic!("Expected a BadFormat error")
+  }
+}
+
+
+#[test]
+fn encode_with_payload() {
+  let mut codec = Codec::new();
+
+  let mut tg = Telegram::new_topic("Data").unwrap();
+  tg.add_param("Name", "blob").unwrap();
+
+  let mut buf = BytesMut::new();
+  codec
+    .encode_with_payload(&tg, b"hello, world", "Len", &mut buf)
+    .unwrap();
+
+  // The caller's telegram is left untouched
+  assert!(!tg.have_param("Len"));
+
+  let len = match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("Data"));
+      assert_eq!(tg.get_str("Name"), Some("blob"));
+      tg.get_param::<usize>("Len").unwrap()
+    }
+    _ => panic!("Not a Telegram")
+  };
+
+  codec.expect_bytes(len).unwrap();
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Bytes(b)) => assert_eq!(&b[..], b"hello, world"),
+    _ => panic!("Not a Bytes")
+  }
+  assert!(buf.is_empty());
+}
+
+#[test]
+fn encode_with_empty_payload() {
+  let mut codec = Codec::new();
+  let tg = Telegram::new_topic("Data").unwrap();
+
+  let mut buf = BytesMut::new();
+  match codec.encode_with_payload(&tg, b"", "Len", &mut buf) {
+    Err(Error::BadFormat(_)) => {}
+    _ => panic!("Expected a BadFormat error")
+  }
+  assert!(buf.is_empty());
+}
+
+
+#[test]
+fn transfer_timing() {
+  let mut codec = Codec::new();
+  assert!(codec.transfer_started_at().is_none());
+  assert_eq!(codec.transfer_bytes_received(), None);
+
+  codec.expect_bytes(4).unwrap();
+  assert!(codec.transfer_started_at().is_some());
+  assert_eq!(codec.transfer_bytes_received(), Some(0));
+
+  let mut buf = BytesMut::from(&b"12"[..]);
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+  assert!(codec.transfer_started_at().is_some());
+  assert_eq!(codec.transfer_bytes_received(), Some(2));
+
+  buf.extend_from_slice(b"34");
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Bytes(b)) => assert_eq!(&b[..], b"1234"),
+    _ => panic!("Not a Bytes")
+  }
+  assert!(codec.transfer_started_at().is_none());
+  assert_eq!(codec.transfer_bytes_received(), None);
+}
+
+
+#[test]
+fn max_value_len() {
+  let mut codec = Codec::new();
+  codec.set_max_value_len(4);
+
+  let mut buf = BytesMut::from(&b"hello\nfoo abcd\n\n"[..]);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_str("foo"), Some("abcd"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+
+  let mut buf = BytesMut::from(&b"hello\nbar abcde\n\n"[..]);
+  match codec.decode(&mut buf) {
+    Err(Error::LimitExceeded(msg)) => assert!(msg.contains("bar")),
+    _ => panic!("Expected a LimitExceeded error")
+  }
+}
+
+
+#[test]
+fn separator_any_whitespace() {
+  let data = &b"hello\ntabbed\tone two\nspaced three\tfour\n\n"[..];
+
+  // By default the tab ends up in the key, which is invalid
+  let mut codec = Codec::new();
+  let mut buf = BytesMut::from(data);
+  match codec.decode(&mut buf) {
+    Err(Error::BadFormat(_)) => {}
+    _ => panic!("Expected a BadFormat error")
+  }
+
+  let mut codec = Codec::new();
+  codec.set_separator_any_whitespace(true);
+  let mut buf = BytesMut::from(data);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.num_params(), 2);
+      assert_eq!(tg.get_str("tabbed"), Some("one two"));
+      assert_eq!(tg.get_str("spaced"), Some("three\tfour"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+
+#[test]
+fn utf8_split_across_reads() {
+  let data = "hellö\nnäme Björn\n\n".as_bytes();
+
+  // Feed the data one byte at a time, which splits every multi-byte
+  // character across two reads.
+  let mut codec = Codec::new();
+  let mut buf = BytesMut::new();
+  let mut tg = None;
+  for b in data {
+    assert!(tg.is_none());
+    buf.extend_from_slice(&[*b]);
+    if let Some(codec::Input::Telegram(t)) = codec.decode(&mut buf).unwrap() {
+      tg = Some(t);
+    }
+  }
+
+  let tg = tg.unwrap();
+  assert_eq!(tg.get_topic(), Some("hellö"));
+  assert_eq!(tg.get_str("näme"), Some("Björn"));
+
+  // Same for a Params buffer
+  let data = "näme Björn\n\n".as_bytes();
+  codec.expect_params();
+  let (first, second) = data.split_at(2);
+  let mut buf = BytesMut::from(first);
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+  buf.extend_from_slice(second);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Params(params)) => {
+      assert_eq!(params.get_str("näme"), Some("Björn"));
+    }
+    _ => panic!("Not a Params")
+  }
+}
+
+
+#[test]
+fn encode_batch() {
+  let mut codec = Codec::new();
+
+  let mut tgs = Vec::new();
+  for topic in &["one", "two", "three"] {
+    let mut tg = Telegram::new_topic(topic).unwrap();
+    tg.add_param("name", topic).unwrap();
+    tgs.push(tg);
+  }
+
+  let mut buf = BytesMut::new();
+  codec.encode_batch(&tgs, &mut buf).unwrap();
+
+  for topic in &["one", "two", "three"] {
+    match codec.decode(&mut buf).unwrap() {
+      Some(codec::Input::Telegram(tg)) => {
+        assert_eq!(tg.get_topic(), Some(*topic));
+        assert_eq!(tg.get_str("name"), Some(*topic));
+      }
+      _ => panic!("Not a Telegram")
+    }
+  }
+  assert!(buf.is_empty());
+}
+
+
+#[test]
+fn skip_line() {
+  let mut codec = Codec::new();
+  codec.expect_skip_line();
+  assert_eq!(codec.mode(), Mode::SkipLine);
+
+  let mut buf = BytesMut::from(&b"garbage that"[..]);
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+  buf.extend_from_slice(b" spans reads\nhello\nfoo bar\n\n");
+
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::SkipLineDone) => {}
+    _ => panic!("Not a SkipLineDone")
+  }
+  assert_eq!(codec.mode(), Mode::Telegram);
+
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+      assert_eq!(tg.get_str("foo"), Some("bar"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+
+#[test]
+fn skip_line_max_length() {
+  let mut codec = Codec::new_with_max_length(8);
+  codec.expect_skip_line();
+
+  let mut buf = BytesMut::from(&b"this line is too long"[..]);
+  match codec.decode(&mut buf) {
+    Err(Error::BadFormat(_)) => {}
+    _ => panic!("Expected a BadFormat error")
+  }
+}
+
+
+#[test]
+fn continuations() {
+  let data =
+    &b"hello\ndescription a long\n  value spanning\n\tthree lines\nfoo bar\n\n"
+      [..];
+
+  let mut codec = Codec::new();
+  codec.set_allow_continuations(true);
+
+  let mut buf = BytesMut::from(data);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.num_params(), 2);
+      assert_eq!(
+        tg.get_str("description"),
+        Some("a long value spanning three lines")
+      );
+      assert_eq!(tg.get_str("foo"), Some("bar"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+
+  codec.expect_kvlines();
+  let mut buf = BytesMut::from(&b"foo 1\nfoo 2\n 3\n\n"[..]);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::KVLines(kvlines)) => {
+      assert_eq!(kvlines.serialize().unwrap(), b"foo 1\nfoo 2 3\n\n");
+    }
+    _ => panic!("Not a KVLines")
+  }
+
+  // A continuation line requires a preceding key/value line
+  codec.expect_params();
+  let mut buf = BytesMut::from(&b" orphan\n\n"[..]);
+  match codec.decode(&mut buf) {
+    Err(Error::BadFormat(_)) => {}
+    _ => panic!("Expected a BadFormat error")
+  }
+}
+
+
+#[test]
+fn max_params() {
+  let mut codec = Codec::new();
+  codec.set_max_params(2);
+
+  // Replacing an existing key doesn't count as a new parameter
+  let mut buf = BytesMut::from(&b"hello\na 1\nb 2\na 3\n\n"[..]);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.num_params(), 2);
+    }
+    _ => panic!("Not a Telegram")
+  }
+
+  let mut buf = BytesMut::from(&b"hello\na 1\nb 2\nc 3\n\n"[..]);
+  match codec.decode(&mut buf) {
+    Err(Error::LimitExceeded(_)) => {}
+    _ => panic!("Expected a LimitExceeded error")
+  }
+
+  let mut codec = Codec::new();
+  codec.set_max_params(2);
+  codec.expect_kvlines();
+  let mut buf = BytesMut::from(&b"a 1\na 2\na 3\n\n"[..]);
+  match codec.decode(&mut buf) {
+    Err(Error::LimitExceeded(_)) => {}
+    _ => panic!("Expected a LimitExceeded error")
+  }
+}
+
+
+#[test]
+fn skip_leading_blanks() {
+  let mut codec = Codec::new();
+  codec.set_skip_leading_blanks(true);
+
+  let mut buf = BytesMut::from(&b"\n\nhello\n\n\r\n\nworld\n\n"[..]);
+  for topic in &["hello", "world"] {
+    match codec.decode(&mut buf).unwrap() {
+      Some(codec::Input::Telegram(tg)) => {
+        assert_eq!(tg.get_topic(), Some(*topic));
+      }
+      _ => panic!("Not a Telegram")
+    }
+  }
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+  assert!(buf.is_empty());
+}
+
+
+#[test]
+fn max_file_size() {
+  let mut codec = Codec::new();
+  codec.set_max_file_size(1024);
+
+  let pathname = std::env::temp_dir().join("blather-max-file-size.bin");
+  match codec.expect_file(&pathname, 1025) {
+    Err(Error::InvalidSize(_)) => {}
+    _ => panic!("Expected an InvalidSize error")
+  }
+  assert!(!pathname.exists());
+  assert_eq!(codec.mode(), Mode::Telegram);
+}
+
+
+fn terminator_roundtrip(terminator: Terminator, expected: &[u8]) {
+  let mut codec = Codec::new();
+  codec.set_block_terminator(terminator);
+
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  tg.add_param("dot", ".").unwrap();
+  let mut params = Params::new();
+  params.add_param("foo", "bar").unwrap();
+  let kvlines = KVLines::builder().line(".", "x").build();
+
+  let mut buf = BytesMut::new();
+  codec.encode(&tg, &mut buf).unwrap();
+  assert_eq!(&buf[..], expected);
+  codec.encode(&params, &mut buf).unwrap();
+  codec.encode(&kvlines, &mut buf).unwrap();
+
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+      assert_eq!(tg.get_str("dot"), Some("."));
+    }
+    _ => panic!("Not a Telegram")
+  }
+
+  codec.expect_params();
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Params(params)) => {
+      assert_eq!(params.get_str("foo"), Some("bar"));
+    }
+    _ => panic!("Not a Params")
+  }
+
+  codec.expect_kvlines();
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::KVLines(kvlines)) => {
+      assert_eq!(kvlines.serialize().unwrap(), b". x\n\n");
+    }
+    _ => panic!("Not a KVLines")
+  }
+  assert!(buf.is_empty());
+}
+
+#[test]
+fn block_terminator() {
+  terminator_roundtrip(Terminator::BlankLine, b"hello\ndot .\n\n");
+  terminator_roundtrip(Terminator::DotLine, b"hello\ndot .\n.\n");
+}
+
+
+#[test]
+fn dot_line_ignores_blanks() {
+  let mut codec = Codec::new();
+  codec.set_block_terminator(Terminator::DotLine);
+
+  let mut buf = BytesMut::from(&b"\nhello\n\nfoo bar\r\n\n.\r\n"[..]);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+      assert_eq!(tg.get_str("foo"), Some("bar"));
+      assert_eq!(tg.num_params(), 1);
+    }
+    _ => panic!("Not a Telegram")
+  }
+  assert!(buf.is_empty());
+}
+
+
+#[test]
+fn dot_line_resync() {
+  let mut codec = Codec::new();
+  codec.set_block_terminator(Terminator::DotLine);
+  codec.set_resync_on_error(true);
+
+  let mut buf =
+    BytesMut::from(&b"1bad\nfoo bar\n\n.\nhello\nfoo bar\n.\n"[..]);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::DecodeError(Error::BadFormat(_))) => {}
+    _ => panic!("Not a DecodeError")
+  }
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+
+#[test]
+fn params_count() {
+  let mut codec = Codec::new();
+  codec.expect_params_count(3);
+  assert_eq!(codec.mode(), Mode::Params);
+
+  let mut buf = BytesMut::from(&b"a 1\nb 2\n"[..]);
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+
+  // The empty line is the start of the following telegram, not a
+  // terminator.
+  buf.extend_from_slice(b"c 3\nhello\n\n");
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Params(params)) => {
+      assert_eq!(params.len(), 3);
+      assert_eq!(params.get_str("a"), Some("1"));
+      assert_eq!(params.get_str("c"), Some("3"));
+    }
+    _ => panic!("Not a Params")
+  }
+  assert_eq!(codec.mode(), Mode::Telegram);
+
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+
+#[test]
+fn params_count_zero() {
+  let mut codec = Codec::new();
+  codec.expect_params_count(0);
+
+  let mut buf = BytesMut::new();
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Params(params)) => assert!(params.is_empty()),
+    _ => panic!("Not a Params")
+  }
+  assert_eq!(codec.mode(), Mode::Telegram);
+}
+
+
+#[test]
+fn strict_binary_alignment() {
+  let mut codec = Codec::new();
+  codec.set_strict_binary_alignment(true);
+
+  // Aligned; the telegram follows the binary data immediately.
+  codec.expect_bytes(4).unwrap();
+  let mut buf = BytesMut::from(&b"1234Hello\n\n"[..]);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Bytes(bytes)) => assert_eq!(&bytes[..], b"1234"),
+    _ => panic!("Not Bytes")
+  }
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("Hello"))
+    }
+    _ => panic!("Not a Telegram")
+  }
+
+  // Peer sent more data than announced.  The trailing garbage arrives in a
+  // separate read.
+  codec.expect_bytes(4).unwrap();
+  let mut buf = BytesMut::from(&b"1234"[..]);
+  assert!(matches!(
+    codec.decode(&mut buf).unwrap(),
+    Some(codec::Input::Bytes(_))
+  ));
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+  buf.extend_from_slice(b"56Hello\n\n");
+  assert!(matches!(codec.decode(&mut buf), Err(Error::BadFormat(_))));
+
+  // Trailing garbage in the same read.
+  let mut codec = Codec::new();
+  codec.set_strict_binary_alignment(true);
+  codec.expect_bytesmut(2).unwrap();
+  let mut buf = BytesMut::from(&b"12\0\0Hello\n\n"[..]);
+  assert!(matches!(
+    codec.decode(&mut buf).unwrap(),
+    Some(codec::Input::BytesMut(_))
+  ));
+  assert!(matches!(codec.decode(&mut buf), Err(Error::BadFormat(_))));
+}
+
+
+#[test]
+fn compact_topic() {
+  let mut codec = Codec::new();
+  codec.set_compact_topic(true);
+
+  let mut buf = BytesMut::from(&b"Move;x 10\ny 20\n\n"[..]);
+  let tg = match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => tg,
+    _ => panic!("Not a Telegram")
+  };
+  assert_eq!(tg.get_topic(), Some("Move"));
+  assert_eq!(tg.num_params(), 2);
+  assert_eq!(tg.get_int::<u32>("x").unwrap(), 10);
+  assert_eq!(tg.get_int::<u32>("y").unwrap(), 20);
+
+  // The encoder writes the regular format, which the decoder still accepts.
+  let mut buf = BytesMut::new();
+  codec.encode(&tg, &mut buf).unwrap();
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg2)) => {
+      assert_eq!(tg2.get_topic(), Some("Move"));
+      assert_eq!(tg2.get_params().get_inner(), tg.get_params().get_inner());
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+
+#[test]
+fn compact_topic_no_segment() {
+  let mut codec = Codec::new();
+  codec.set_compact_topic(true);
+
+  let mut buf = BytesMut::from(&b"Stop\nx 10\n\nHalt;\n\n"[..]);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("Stop"));
+      assert_eq!(tg.num_params(), 1);
+    }
+    _ => panic!("Not a Telegram")
+  }
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("Halt"));
+      assert!(tg.get_params().is_empty());
+    }
+    _ => panic!("Not a Telegram")
+  }
+
+  // Without compact topics enabled the ';' is an invalid topic character.
+  let mut codec = Codec::new();
+  let mut buf = BytesMut::from(&b"Move;x 10\n\n"[..]);
+  assert!(matches!(codec.decode(&mut buf), Err(Error::BadFormat(_))));
+}
+
+
+#[test]
+fn pause_chunks() {
+  let mut codec = Codec::new();
+  codec.expect_chunks(10);
+
+  let mut buf = BytesMut::from(&b"0123"[..]);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Chunk(chunk, remain)) => {
+      assert_eq!(&chunk[..], b"0123");
+      assert_eq!(remain, 6);
+    }
+    _ => panic!("Not a Chunk")
+  }
+
+  codec.pause();
+  assert!(codec.is_paused());
+  buf.extend_from_slice(b"456");
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+  buf.extend_from_slice(b"789");
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+  assert_eq!(&buf[..], b"456789");
+  assert_eq!(codec.mode(), Mode::Chunks);
+
+  codec.resume();
+  assert!(!codec.is_paused());
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Chunk(chunk, remain)) => {
+      assert_eq!(&chunk[..], b"456789");
+      assert_eq!(remain, 0);
+    }
+    _ => panic!("Not a Chunk")
+  }
+  assert_eq!(codec.mode(), Mode::Telegram);
+}
+
+
+#[test]
+fn expect_telegram() {
+  let mut codec = Codec::new();
+  codec.expect_params();
+  assert_eq!(codec.mode(), Mode::Params);
+
+  codec.expect_telegram();
+  assert_eq!(codec.mode(), Mode::Telegram);
+
+  let mut buf = BytesMut::from(&b"hello
+foo bar
+
+"[..]);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+      assert_eq!(tg.get_str("foo"), Some("bar"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+
+#[test]
+fn expect_telegram_discards_partial() {
+  let mut codec = Codec::new();
+
+  let mut buf = BytesMut::from(&b"stale
+foo bar
+"[..]);
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+
+  codec.expect_telegram();
+  buf.extend_from_slice(b"hello
+
+");
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+      assert_eq!(tg.num_params(), 0);
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+
+#[test]
+fn input_variant_name() {
+  let mut codec = Codec::new();
+  let mut buf = BytesMut::from(&b"hello\n\n0123"[..]);
+
+  let input = codec.decode(&mut buf).unwrap().unwrap();
+  assert_eq!(input.variant_name(), "Telegram");
+  assert!(format!("{:?}", input).starts_with("Telegram("));
+
+  codec.expect_chunks(4);
+  let input = codec.decode(&mut buf).unwrap().unwrap();
+  assert_eq!(input.variant_name(), "Chunk");
+}
+
+
+#[test]
+fn expect_vec() {
+  let mut codec = Codec::new();
+  assert!(matches!(codec.expect_vec(0), Err(Error::InvalidSize(_))));
+
+  codec.expect_vec(4).unwrap();
+  assert_eq!(codec.mode(), Mode::Vec);
+
+  let mut buf = BytesMut::from(&b"12"[..]);
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+
+  buf.extend_from_slice(b"34hello\n\n");
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Vec(v)) => assert_eq!(v, b"1234".to_vec()),
+    _ => panic!("Not a Vec")
+  }
+  assert_eq!(codec.mode(), Mode::Telegram);
+
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+
+#[test]
+fn repeated_key_join() {
+  let data = &b"hello\ntag a\nname frank\ntag b\ntag c\n\n"[..];
+
+  let mut codec = Codec::new();
+  codec.set_repeated_key_join(Some(','));
+  let mut buf = BytesMut::from(data);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.num_params(), 2);
+      assert_eq!(tg.get_str("tag"), Some("a,b,c"));
+      assert_eq!(tg.get_strvec("tag").unwrap(), vec!["a", "b", "c"]);
+      assert_eq!(tg.get_str("name"), Some("frank"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+
+  codec.expect_params();
+  let mut buf = BytesMut::from(&b"x 1\nx 2\n\n"[..]);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Params(params)) => {
+      assert_eq!(params.get_str("x"), Some("1,2"));
+    }
+    _ => panic!("Not a Params")
+  }
+
+  // Disabled; later value wins
+  codec.set_repeated_key_join(None);
+  let mut buf = BytesMut::from(data);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_str("tag"), Some("c"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+
+/// Writer which stores written data in a shared buffer, so it can be
+/// inspected once the codec has dropped the writer.
+struct SharedVec(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedVec {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    self.0.lock().unwrap().extend_from_slice(buf);
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+#[test]
+fn expect_tee() {
+  let written = Arc::new(Mutex::new(Vec::new()));
+
+  let mut codec = Codec::new();
+  codec.expect_tee(SharedVec(Arc::clone(&written)), 8).unwrap();
+  assert_eq!(codec.mode(), Mode::Tee);
+
+  let mut buf = BytesMut::from(&b"0123"[..]);
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+  assert_eq!(&written.lock().unwrap()[..], b"0123");
+
+  buf.extend_from_slice(b"4567hello\n\n");
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Bytes(bytes)) => assert_eq!(&bytes[..], b"01234567"),
+    _ => panic!("Not Bytes")
+  }
+  assert_eq!(&written.lock().unwrap()[..], b"01234567");
+  assert_eq!(codec.mode(), Mode::Telegram);
+
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+
+#[test]
+fn expect_sink() {
+  let sum = Arc::new(Mutex::new((0u64, 0usize)));
+
+  let mut codec = Codec::new();
+  let acc = Arc::clone(&sum);
+  codec
+    .expect_sink(
+      move |chunk: &[u8]| {
+        let mut acc = acc.lock().unwrap();
+        acc.0 += chunk.iter().map(|b| *b as u64).sum::<u64>();
+        acc.1 += 1;
+      },
+      6
+    )
+    .unwrap();
+  assert_eq!(codec.mode(), Mode::Sink);
+
+  let mut buf = BytesMut::from(&[1u8, 2, 3][..]);
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+  assert_eq!(*sum.lock().unwrap(), (6, 1));
+
+  buf.extend_from_slice(&[4, 5]);
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+
+  buf.extend_from_slice(b"\x06hello\n\n");
+  assert!(matches!(
+    codec.decode(&mut buf).unwrap(),
+    Some(codec::Input::SinkDone)
+  ));
+  assert_eq!(*sum.lock().unwrap(), (21, 3));
+  assert_eq!(codec.mode(), Mode::Telegram);
+
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+
+#[test]
+fn greeting() {
+  let mut codec = Codec::new();
+  codec.expect_greeting();
+  assert_eq!(codec.mode(), Mode::Greeting);
+
+  let mut buf = BytesMut::from(&b"BLATHER/1"[..]);
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+
+  buf.extend_from_slice(b".0\r\nHello\nfoo bar\n\n");
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Greeting(line)) => assert_eq!(line, "BLATHER/1.0"),
+    _ => panic!("Not a Greeting")
+  }
+  assert_eq!(codec.mode(), Mode::Telegram);
+
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("Hello"));
+      assert_eq!(tg.get_str("foo"), Some("bar"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+
+#[test]
+fn on_telegram() {
+  let topics = Arc::new(Mutex::new(Vec::new()));
+
+  let mut codec = Codec::new();
+  let t = Arc::clone(&topics);
+  codec.set_on_telegram(move |tg| {
+    t.lock().unwrap().push(tg.get_topic().unwrap().to_string());
+  });
+
+  let mut buf = BytesMut::from(&b"one\n\nfoo bar\n\ntwo\n\n"[..]);
+  assert!(matches!(
+    codec.decode(&mut buf).unwrap(),
+    Some(codec::Input::Telegram(_))
+  ));
+
+  // Other buffers are not observed
+  codec.expect_params();
+  assert!(matches!(
+    codec.decode(&mut buf).unwrap(),
+    Some(codec::Input::Params(_))
+  ));
+
+  assert!(matches!(
+    codec.decode(&mut buf).unwrap(),
+    Some(codec::Input::Telegram(_))
+  ));
+  assert_eq!(*topics.lock().unwrap(), vec!["one", "two"]);
+
+  codec.clear_on_telegram();
+  let mut buf = BytesMut::from(&b"three\n\n"[..]);
+  assert!(codec.decode(&mut buf).unwrap().is_some());
+  assert_eq!(topics.lock().unwrap().len(), 2);
+}
+
+
+#[test]
+fn stats() {
+  let mut codec = Codec::new();
+  assert_eq!(codec.stats(), CodecStats::default());
+
+  let mut buf = BytesMut::from(&b"one\n\ntwo\nlen 6\n\n"[..]);
+  assert!(codec.decode(&mut buf).unwrap().is_some());
+  assert!(codec.decode(&mut buf).unwrap().is_some());
+
+  codec.expect_bytes(6).unwrap();
+  buf.extend_from_slice(b"012");
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+  buf.extend_from_slice(b"345a 1\n\n");
+  assert!(codec.decode(&mut buf).unwrap().is_some());
+
+  codec.expect_params();
+  assert!(codec.decode(&mut buf).unwrap().is_some());
+
+  buf.extend_from_slice(b"bad topic\n\n");
+  assert!(codec.decode(&mut buf).is_err());
+
+  assert_eq!(
+    codec.stats(),
+    CodecStats {
+      telegrams: 2,
+      params: 1,
+      kvlines: 0,
+      binary_bytes: 6,
+      errors: 1
+    }
+  );
+}
+
+
+#[test]
+fn param_multi() {
+  let mut codec = Codec::new();
+  codec.set_repeated_key_join(Some('|'));
+  let mut buf =
+    BytesMut::from(&b"hello\ntag a,b\ntag \ntag c\nname frank\n\n"[..]);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_str("tag"), Some("a,b||c"));
+      assert_eq!(tg.get_param_multi("tag", '|'), vec!["a,b", "", "c"]);
+      assert_eq!(tg.get_param_multi("name", '|'), vec!["frank"]);
+      assert!(tg.get_param_multi("missing", '|').is_empty());
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+
+#[test]
+fn topic_kvlines() {
+  let mut kvlines = KVLines::new();
+  kvlines.append("step", "fetch");
+  kvlines.append("step", "build");
+  kvlines.append("mode", "release");
+
+  let mut codec = Codec::new();
+  let mut buf = BytesMut::new();
+  codec.encode(("Pipeline", &kvlines), &mut buf).unwrap();
+  assert_eq!(
+    &buf[..],
+    &b"Pipeline\nstep fetch\nstep build\nmode release\n\n"[..]
+  );
+  assert!(codec.encode(("bad topic", &kvlines), &mut buf).is_err());
+
+  // Receive in two parts, split within the key/value lines
+  let rest = buf.split_off(20);
+  codec.expect_topic_kvlines();
+  assert_eq!(codec.mode(), Mode::TopicKVLines);
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+  buf.unsplit(rest);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::TopicKVLines(topic, kvl)) => {
+      assert_eq!(topic, "Pipeline");
+      assert_eq!(kvl.get_inner(), kvlines.get_inner());
+    }
+    _ => panic!("Not a TopicKVLines")
+  }
+  assert_eq!(codec.mode(), Mode::Telegram);
+
+  codec.expect_topic_kvlines();
+  let mut buf = BytesMut::from(&b"\nfoo bar\n\n"[..]);
+  assert!(matches!(codec.decode(&mut buf), Err(Error::BadFormat(_))));
+}
+
+
+#[test]
+fn callbacks_not_sync() {
+  fn assert_send_sync<T: Send + Sync>() {}
+  assert_send_sync::<Codec>();
+
+  let (tx, rx) = mpsc::channel();
+  let count = Cell::new(0);
+
+  let mut codec = Codec::new();
+  codec.set_on_telegram(move |_tg| {
+    count.set(count.get() + 1);
+    tx.send(count.get()).unwrap();
+  });
+  let mut buf = BytesMut::from(&b"one\n\ntwo\n\n0123"[..]);
+  assert!(codec.decode(&mut buf).unwrap().is_some());
+  assert!(codec.decode(&mut buf).unwrap().is_some());
+  assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+
+  let (tx, rx) = mpsc::channel();
+  codec
+    .expect_sink(move |chunk| tx.send(chunk.to_vec()).unwrap(), 4)
+    .unwrap();
+  assert!(matches!(
+    codec.decode(&mut buf).unwrap(),
+    Some(codec::Input::SinkDone)
+  ));
+  assert_eq!(rx.try_iter().collect::<Vec<_>>().concat(), b"0123");
+}
+
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -1,8 +1,12 @@
+#![cfg(feature = "codec")]
+
+use std::time::Duration;
+
 use tokio_stream::StreamExt;
 
 use tokio_test::io::Builder;
 
-use tokio_util::codec::Framed;
+use tokio_util::codec::{Decoder, Framed};
 
 use blather::{codec, Codec, Error};
 
@@ -83,4 +87,137 @@ async fn bad_topic() {
 }
 
 
+#[tokio::test]
+async fn resync_after_bad_telegram() {
+  let mut mock = Builder::new();
+
+  mock.read(b"hel lo\nfoo b");
+  mock.read(b"ar\n\nhello\nfoo bar\n\n");
+
+  let mut codec = Codec::new();
+  codec.set_resync_on_error(true);
+  let mut frm = Framed::new(mock.build(), codec);
+
+  match frm.next().await {
+    Some(Ok(codec::Input::DecodeError(Error::BadFormat(s)))) => {
+      assert_eq!(s, "Invalid topic character");
+    }
+    _ => panic!("Expected a DecodeError")
+  }
+
+  match frm.next().await {
+    Some(Ok(codec::Input::Telegram(tg))) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+      assert_eq!(tg.get_str("foo"), Some("bar"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+
+  assert!(frm.next().await.is_none());
+}
+
+
+#[tokio::test]
+async fn resync_after_invalid_utf8() {
+  let mut mock = Builder::new();
+
+  mock.read(b"hello\nfoo b\xffr\nmoo cow\n\nhello\nfoo bar\n\n");
+
+  let mut codec = Codec::new();
+  codec.set_resync_on_error(true);
+  let mut frm = Framed::new(mock.build(), codec);
+
+  match frm.next().await {
+    Some(Ok(codec::Input::DecodeError(Error::IO(_)))) => {}
+    _ => panic!("Expected a DecodeError")
+  }
+
+  match frm.next().await {
+    Some(Ok(codec::Input::Telegram(tg))) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+      assert_eq!(tg.num_params(), 1);
+      assert_eq!(tg.get_str("foo"), Some("bar"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+
+  assert!(frm.next().await.is_none());
+}
+
+
+#[tokio::test]
+async fn leading_bom() {
+  let mut mock = Builder::new();
+
+  mock.read(b"\xef\xbb");
+  mock.read(b"\xbfhello\n\n");
+
+  let mut codec = Codec::new();
+  codec.set_strip_bom(true);
+  let mut frm = Framed::new(mock.build(), codec);
+
+  match frm.next().await {
+    Some(Ok(codec::Input::Telegram(tg))) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+
+  assert!(frm.next().await.is_none());
+}
+
+
+#[tokio::test]
+async fn leading_bom_disabled() {
+  let mut mock = Builder::new();
+
+  mock.read(b"\xef\xbb\xbfhello\n\n");
+
+  // Disabled by default
+  let mut frm = Framed::new(mock.build(), Codec::new());
+
+  match frm.next().await {
+    Some(Err(Error::BadFormat(_))) => {}
+    _ => panic!("Expected a BadFormat error")
+  }
+}
+
+
+#[tokio::test]
+async fn resume_buffered() {
+  let mut mock = Builder::new();
+
+  // The peer sends two telegrams and then waits for a reply.
+  mock.read(b"one\n\ntwo\n\n").wait(Duration::from_secs(3600));
+
+  let mut frm = Framed::new(mock.build(), Codec::new());
+
+  match frm.next().await {
+    Some(Ok(codec::Input::Telegram(tg))) => {
+      assert_eq!(tg.get_topic(), Some("one"))
+    }
+    _ => panic!("Not a Telegram")
+  }
+
+  // Polling while paused returns no frames.
+  frm.codec_mut().pause();
+  let res = tokio::time::timeout(Duration::from_millis(20), frm.next()).await;
+  assert!(res.is_err());
+  assert_eq!(&frm.read_buffer()[..], b"two\n\n");
+
+  // Receive the buffered telegram without any further input.
+  frm.codec_mut().resume();
+  let mut buf = frm.read_buffer_mut().split();
+  let input = frm.codec_mut().decode(&mut buf);
+  *frm.read_buffer_mut() = buf;
+  match input.unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("two"))
+    }
+    _ => panic!("Not a Telegram")
+  }
+  assert!(frm.read_buffer().is_empty());
+}
+
+
 // vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
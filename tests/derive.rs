@@ -0,0 +1,58 @@
+#![cfg(feature = "derive")]
+
+use blather::{Error, FromTelegram, Telegram};
+
+#[derive(Debug, FromTelegram)]
+struct Config {
+  port: u16,
+  name: Option<String>,
+  #[blather(default)]
+  verbose: bool,
+  #[blather(rename = "Max-Conns", default)]
+  max_conns: u32
+}
+
+#[test]
+fn all_fields() {
+  let mut tg = Telegram::new_topic("Config").unwrap();
+  tg.add_param("port", 8080).unwrap();
+  tg.add_param("name", "frank").unwrap();
+  tg.add_param("verbose", "true").unwrap();
+  tg.add_param("Max-Conns", 16).unwrap();
+
+  let cfg = Config::from_telegram(&tg).unwrap();
+  assert_eq!(cfg.port, 8080);
+  assert_eq!(cfg.name.as_deref(), Some("frank"));
+  assert!(cfg.verbose);
+  assert_eq!(cfg.max_conns, 16);
+}
+
+#[test]
+fn optional_and_defaults() {
+  let mut tg = Telegram::new_topic("Config").unwrap();
+  tg.add_param("port", 8080).unwrap();
+
+  let cfg = Config::from_telegram(&tg).unwrap();
+  assert_eq!(cfg.port, 8080);
+  assert_eq!(cfg.name, None);
+  assert!(!cfg.verbose);
+  assert_eq!(cfg.max_conns, 0);
+}
+
+#[test]
+fn missing_required() {
+  let tg = Telegram::new_topic("Config").unwrap();
+  assert_eq!(
+    Config::from_telegram(&tg).unwrap_err(),
+    Error::KeyNotFound("port".to_string())
+  );
+}
+
+#[test]
+fn bad_value() {
+  let mut tg = Telegram::new_topic("Config").unwrap();
+  tg.add_param("port", "http").unwrap();
+  assert!(matches!(Config::from_telegram(&tg), Err(Error::BadFormat(_))));
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
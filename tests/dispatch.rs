@@ -0,0 +1,47 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use blather::{Error, Telegram, TopicDispatcher};
+
+#[test]
+fn dispatch_by_topic() {
+  let log = Rc::new(RefCell::new(Vec::new()));
+
+  let mut dispatcher = TopicDispatcher::new();
+
+  let l = Rc::clone(&log);
+  dispatcher.register("Hello", move |tg| {
+    l.borrow_mut().push(format!("hello:{}", tg.get_str("Name").unwrap()));
+  });
+
+  let l = Rc::clone(&log);
+  dispatcher.register("Bye", move |tg| {
+    l.borrow_mut().push(format!("bye:{}", tg.get_str("Name").unwrap()));
+  });
+
+  let mut tg = Telegram::new_topic("Hello").unwrap();
+  tg.add_param("Name", "Elena").unwrap();
+  dispatcher.dispatch(tg).unwrap();
+
+  let mut tg = Telegram::new_topic("Bye").unwrap();
+  tg.add_param("Name", "Drake").unwrap();
+  dispatcher.dispatch(tg).unwrap();
+
+  assert_eq!(*log.borrow(), vec!["hello:Elena", "bye:Drake"]);
+}
+
+
+#[test]
+fn unregistered_topic() {
+  let mut dispatcher = TopicDispatcher::new();
+  dispatcher.register("Hello", |_tg| {});
+
+  let tg = Telegram::new_topic("Unknown").unwrap();
+  assert_eq!(
+    dispatcher.dispatch(tg),
+    Err(Error::KeyNotFound("Unknown".to_string()))
+  );
+}
+
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
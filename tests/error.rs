@@ -0,0 +1,48 @@
+use blather::{Error, Params};
+
+#[test]
+fn with_context() {
+  let params = Params::new();
+
+  let e = params
+    .get_param::<u32>("Age")
+    .map_err(|e| e.with_context("AddUser"))
+    .unwrap_err();
+
+  assert_eq!(e, Error::KeyNotFound("AddUser: Age".to_string()));
+
+  let s = e.to_string();
+  assert!(s.contains("AddUser"));
+  assert!(s.contains("Age"));
+  assert!(s.contains("not found"));
+}
+
+
+#[test]
+fn limit_exceeded() {
+  let e = Error::LimitExceeded("More than 8 parameters".to_string());
+  assert_eq!(e.to_string(), "Limit exceeded; More than 8 parameters");
+
+  let e = e.with_context("conn 7");
+  assert_eq!(
+    e,
+    Error::LimitExceeded("conn 7: More than 8 parameters".to_string())
+  );
+}
+
+
+#[test]
+fn recoverable() {
+  let s = || "x".to_string();
+  assert!(Error::KeyNotFound(s()).is_recoverable());
+  assert!(Error::BadFormat(s()).is_recoverable());
+  assert!(Error::SerializeError(s()).is_recoverable());
+  assert!(Error::InvalidSize(s()).is_recoverable());
+  assert!(Error::LimitExceeded(s()).is_recoverable());
+  assert!(!Error::IO(s()).is_recoverable());
+  assert!(!Error::BadState(s()).is_recoverable());
+  assert!(!Error::Incomplete(s()).is_recoverable());
+}
+
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
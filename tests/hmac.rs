@@ -0,0 +1,136 @@
+#![cfg(feature = "hmac")]
+
+use bytes::BytesMut;
+
+use hmac::{Hmac, Mac};
+
+use sha2::Sha256;
+
+use tokio_util::codec::{Decoder, Encoder};
+
+use blather::{codec, Codec, Error, Telegram};
+
+fn hmac_hex(key: &[u8], data: &[u8]) -> String {
+  let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+  mac.update(data);
+  format!("{:x}", mac.finalize().into_bytes())
+}
+
+fn hmac_encode(integrity: bool) -> BytesMut {
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  tg.add_param("foo", "bar").unwrap();
+  tg.add_param("moo", "cow").unwrap();
+
+  let mut codec = Codec::new();
+  codec.set_hmac_key("secret");
+  codec.set_integrity(integrity);
+
+  let mut buf = BytesMut::new();
+  codec.encode(&tg, &mut buf).unwrap();
+  buf
+}
+
+fn expect_bad_format(codec: &mut Codec, buf: &mut BytesMut, msg: &str) {
+  match codec.decode(buf) {
+    Err(Error::BadFormat(s)) => assert_eq!(s, msg),
+    _ => panic!("Expected a BadFormat error")
+  }
+}
+
+#[test]
+fn roundtrip() {
+  for integrity in &[false, true] {
+    let mut buf = hmac_encode(*integrity);
+
+    let mut codec = Codec::new();
+    codec.set_hmac_key(b"secret");
+    codec.set_integrity(*integrity);
+
+    match codec.decode(&mut buf).unwrap() {
+      Some(codec::Input::Telegram(tg)) => {
+        assert_eq!(tg.get_topic(), Some("hello"));
+        assert_eq!(tg.num_params(), 2);
+        assert_eq!(tg.get_str("foo"), Some("bar"));
+        assert_eq!(tg.get_str("moo"), Some("cow"));
+        assert!(!tg.have_param("__hmac"));
+      }
+      _ => panic!("Not a Telegram")
+    }
+    assert!(buf.is_empty());
+  }
+}
+
+#[test]
+fn tampered() {
+  let mut buf = hmac_encode(false);
+
+  // Change the value of a parameter
+  let pos = buf.windows(3).position(|w| w == b"bar").unwrap();
+  buf[pos] = b'c';
+
+  let mut codec = Codec::new();
+  codec.set_hmac_key("secret");
+  expect_bad_format(&mut codec, &mut buf, "Authentication check failed");
+}
+
+#[test]
+fn wrong_key() {
+  let mut buf = hmac_encode(false);
+
+  let mut codec = Codec::new();
+  codec.set_hmac_key("guessed");
+  expect_bad_format(&mut codec, &mut buf, "Authentication check failed");
+}
+
+#[test]
+fn missing() {
+  let mut buf = BytesMut::from(&b"hello\nfoo bar\n\n"[..]);
+
+  let mut codec = Codec::new();
+  codec.set_hmac_key("secret");
+  expect_bad_format(&mut codec, &mut buf, "Missing authentication trailer");
+}
+
+#[test]
+fn rfc4231_vectors() {
+  assert_eq!(
+    hmac_hex(b"Jefe", b"what do ya want for nothing?"),
+    "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+  );
+  assert_eq!(
+    hmac_hex(
+      &[0xaa; 131],
+      b"Test Using Larger Than Block-Size Key - Hash Key First"
+    ),
+    "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+  );
+}
+
+#[test]
+fn trailer_covers_body() {
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  tg.add_param("foo", "bar").unwrap();
+
+  let mut codec = Codec::new();
+  codec.set_hmac_key("Jefe");
+  let mut buf = BytesMut::new();
+  codec.encode(&tg, &mut buf).unwrap();
+
+  let expected = format!(
+    "hello\nfoo bar\n__hmac {}\n\n",
+    hmac_hex(b"Jefe", b"hello\nfoo bar\n")
+  );
+  assert_eq!(&buf[..], expected.as_bytes());
+}
+
+#[test]
+fn invalid_trailer() {
+  let mut buf = BytesMut::from(&b"hello\nfoo bar\n__hmac +f\n\n"[..]);
+
+  let mut codec = Codec::new();
+  codec.set_hmac_key("secret");
+  expect_bad_format(&mut codec, &mut buf, "Invalid authentication trailer");
+}
+
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -0,0 +1,55 @@
+#![cfg(feature = "json")]
+
+use bytes::BytesMut;
+
+use tokio_util::codec::Decoder;
+
+use blather::{codec, Codec, Error};
+
+#[test]
+fn flat_object() {
+  let json = br#"{"name": "frank", "age": 42, "admin": false}"#;
+
+  let mut codec = Codec::new();
+  codec.expect_json(json.len()).unwrap();
+
+  let mut buf = BytesMut::from(&json[..10]);
+  assert!(codec.decode(&mut buf).unwrap().is_none());
+  buf.extend_from_slice(&json[10..]);
+  buf.extend_from_slice(b"hello\n\n");
+
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Params(params)) => {
+      assert_eq!(params.len(), 3);
+      assert_eq!(params.get_str("name"), Some("frank"));
+      assert_eq!(params.get_int::<u32>("age").unwrap(), 42);
+      assert_eq!(params.get_bool("admin"), Ok(false));
+    }
+    _ => panic!("Not a Params")
+  }
+
+  // Reverts to expecting a telegram
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+}
+
+
+#[test]
+fn unsupported_values() {
+  for json in &[&b"[1, 2]"[..], br#"{"a": null}"#, br#"{"a": [1]}"#, b"{"] {
+    let mut codec = Codec::new();
+    codec.expect_json(json.len()).unwrap();
+
+    let mut buf = BytesMut::from(*json);
+    match codec.decode(&mut buf) {
+      Err(Error::BadFormat(_)) => {}
+      _ => panic!("Expected a BadFormat error")
+    }
+  }
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -0,0 +1,128 @@
+use blather::{types::DupPolicy, Error, KVLines};
+
+#[test]
+fn empty() {
+  let mut kvlines = KVLines::new();
+  assert!(kvlines.is_empty());
+  assert_eq!(kvlines.len(), 0);
+
+  kvlines.append("foo", "bar");
+  kvlines.append("foo", "baz");
+  assert!(!kvlines.is_empty());
+  assert_eq!(kvlines.len(), 2);
+}
+
+
+#[test]
+fn remove_key() {
+  let mut kvlines = KVLines::builder()
+    .line("foo", "1")
+    .line("bar", "2")
+    .line("foo", "3")
+    .build();
+
+  assert_eq!(kvlines.remove_key("foo"), 2);
+  assert_eq!(kvlines.remove_key("foo"), 0);
+  assert_eq!(kvlines.serialize().unwrap(), b"bar 2\n\n");
+}
+
+
+#[test]
+fn retain() {
+  let mut kvlines = KVLines::builder()
+    .line("a", "1")
+    .line("b", "20")
+    .line("c", "3")
+    .line("d", "40")
+    .build();
+
+  kvlines.retain(|kv| kv.value().len() == 1);
+  assert_eq!(kvlines.serialize().unwrap(), b"a 1\nc 3\n\n");
+}
+
+
+#[test]
+fn extend() {
+  let mut kvlines = KVLines::new();
+  kvlines.extend(vec![("foo", "1"), ("foo", "2")]);
+  kvlines.extend(vec![("bar".to_string(), "3".to_string())]);
+
+  assert_eq!(kvlines.len(), 3);
+  assert_eq!(kvlines.serialize().unwrap(), b"foo 1\nfoo 2\nbar 3\n\n");
+}
+
+
+#[test]
+fn iterate() {
+  let kvlines = KVLines::builder()
+    .line("foo", "1")
+    .line("bar", "2")
+    .line("foo", "3")
+    .build();
+
+  let mut pairs = Vec::new();
+  for (k, v) in &kvlines {
+    pairs.push((k, v));
+  }
+  assert_eq!(pairs, vec![("foo", "1"), ("bar", "2"), ("foo", "3")]);
+  assert_eq!(kvlines.iter().count(), 3);
+
+  let mut owned = Vec::new();
+  for kv in kvlines {
+    owned.push(format!("{}={}", kv.key(), kv.value()));
+  }
+  assert_eq!(owned, vec!["foo=1", "bar=2", "foo=3"]);
+}
+
+
+#[test]
+fn to_params() {
+  let kvlines = KVLines::builder()
+    .line("foo", "1")
+    .line("bar", "2")
+    .line("foo", "3")
+    .build();
+
+  let params = kvlines.to_params(DupPolicy::KeepFirst).unwrap();
+  assert_eq!(params.len(), 2);
+  assert_eq!(params.get_str("foo"), Some("1"));
+  assert_eq!(params.get_str("bar"), Some("2"));
+
+  let params = kvlines.to_params(DupPolicy::KeepLast).unwrap();
+  assert_eq!(params.len(), 2);
+  assert_eq!(params.get_str("foo"), Some("3"));
+  assert_eq!(params.get_str("bar"), Some("2"));
+
+  assert_eq!(
+    kvlines.to_params(DupPolicy::Error).unwrap_err(),
+    Error::BadFormat("Duplicate key 'foo'".to_string())
+  );
+
+  let unique = KVLines::builder().line("a", "1").line("b", "2").build();
+  let params = unique.to_params(DupPolicy::Error).unwrap();
+  assert_eq!(params.len(), 2);
+}
+
+
+#[test]
+fn get_mut() {
+  let mut kvlines = KVLines::builder()
+    .line("name", "frank")
+    .line("tag", "a")
+    .line("tag", "b")
+    .build();
+
+  kvlines.get_mut("tag").unwrap().set_value("c");
+  let kv = kvlines.get_mut("name").unwrap();
+  assert_eq!(kv.key(), "name");
+  kv.set_value(42);
+  assert!(kvlines.get_mut("nonexist").is_none());
+
+  assert_eq!(
+    kvlines.serialize().unwrap(),
+    b"name 42\ntag c\ntag b\n\n"
+  );
+}
+
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
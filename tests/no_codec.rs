@@ -0,0 +1,24 @@
+//! Make sure the communication buffer types are usable when the `codec`
+//! feature has been disabled.  Run using `cargo test --no-default-features`.
+
+#![cfg(not(feature = "codec"))]
+
+use blather::{KVLines, Params, Telegram};
+
+#[test]
+fn types_without_codec() {
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  tg.add_param("foo", "bar").unwrap();
+  assert_eq!(tg.serialize().unwrap(), b"hello\nfoo bar\n\n");
+
+  let mut params = Params::new();
+  params.add_param("foo", "bar").unwrap();
+  assert_eq!(params.serialize().unwrap(), b"foo bar\n\n");
+
+  let mut kvlines = KVLines::new();
+  kvlines.append("foo", "bar");
+  assert_eq!(kvlines.serialize().unwrap(), b"foo bar\n\n");
+}
+
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -1,4 +1,9 @@
-use blather::{Error, Params};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+
+use blather::{Encoding, Error, KeyPolicy, Params};
 
 
 #[test]
@@ -17,9 +22,9 @@ fn exists() {
   let mut params = Params::new();
 
   params.add_str("foo", "bar").unwrap();
-  assert_eq!(params.have("foo"), true);
+  assert!(params.have("foo"));
 
-  assert_eq!(params.have("nonexistent"), false);
+  assert!(!params.have("nonexistent"));
 }
 
 
@@ -32,11 +37,29 @@ fn integer() {
 }
 
 
+#[test]
+fn param_trimmed() {
+  let mut params = Params::new();
+  params.add_str("num", "  42  ").unwrap();
+  params.add_str("word", "  abc  ").unwrap();
+
+  assert_eq!(params.get_param_trimmed::<u32>("num").unwrap(), 42);
+  assert!(matches!(
+    params.get_param_trimmed::<u32>("word"),
+    Err(Error::BadFormat(_))
+  ));
+  assert_eq!(
+    params.get_param_trimmed::<u32>("nonexist"),
+    Err(Error::KeyNotFound("nonexist".to_string()))
+  );
+}
+
+
 #[test]
 fn size() {
   let mut msg = Params::new();
 
-  msg.add_param("Num", 7 as usize).unwrap();
+  msg.add_param("Num", 7_usize).unwrap();
   assert_eq!(msg.get_int::<usize>("Num").unwrap(), 7);
 }
 
@@ -122,4 +145,613 @@ fn boolvals() {
 }
 
 
+#[test]
+fn bytes_roundtrip() {
+  let data: Vec<u8> = (0..=255).collect();
+  let mut params = Params::new();
+
+  for n in 0..4 {
+    let slice = &data[..data.len() - n];
+
+    params.add_bytes("b64", slice, Encoding::Base64).unwrap();
+    assert_eq!(params.get_bytes("b64", Encoding::Base64).unwrap(), slice);
+
+    params.add_bytes("hex", slice, Encoding::Hex).unwrap();
+    assert_eq!(params.get_bytes("hex", Encoding::Hex).unwrap(), slice);
+  }
+}
+
+
+#[test]
+fn bytes_bad_encoding() {
+  let mut params = Params::new();
+
+  params.add_param("b64", "aGVsbG8").unwrap();
+  params.add_param("hex", "xyz0").unwrap();
+
+  assert!(matches!(
+    params.get_bytes("b64", Encoding::Base64),
+    Err(Error::BadFormat(_))
+  ));
+  assert!(matches!(
+    params.get_bytes("hex", Encoding::Hex),
+    Err(Error::BadFormat(_))
+  ));
+}
+
+
+#[test]
+fn empty() {
+  let mut params = Params::new();
+  assert!(params.is_empty());
+  assert_eq!(params.len(), 0);
+
+  params.add_param("foo", "bar").unwrap();
+  assert!(!params.is_empty());
+  assert_eq!(params.len(), 1);
+}
+
+
+#[test]
+fn get_or_insert_with() {
+  let mut params = Params::new();
+  let mut calls = 0;
+
+  let v = params
+    .get_or_insert_with("Name", || {
+      calls += 1;
+      "Elena".to_string()
+    })
+    .unwrap()
+    .to_string();
+  assert_eq!(v, "Elena");
+
+  let v = params
+    .get_or_insert_with("Name", || {
+      calls += 1;
+      "Chloe".to_string()
+    })
+    .unwrap();
+  assert_eq!(v, "Elena");
+  assert_eq!(calls, 1);
+
+  assert_eq!(
+    params.get_or_insert_with("bad key", String::new),
+    Err(Error::BadFormat("Invalid key character".to_string()))
+  );
+  assert!(!params.have("bad key"));
+}
+
+
+#[test]
+fn try_from_bytes() {
+  let buf = b"foo bar\nmoo cow says moo\n\n";
+  let params = Params::try_from(&buf[..]).unwrap();
+
+  assert_eq!(params.len(), 2);
+  assert_eq!(params.get_str("foo"), Some("bar"));
+  assert_eq!(params.get_str("moo"), Some("cow says moo"));
+}
+
+
+#[test]
+fn try_from_bytes_bad_key() {
+  assert_eq!(
+    Params::try_from(&b"foo bar\nf\xc3\xa4\x01 cow\n\n"[..]).unwrap_err(),
+    Error::BadFormat("Invalid key character".to_string())
+  );
+}
+
+
+#[test]
+fn try_from_bytes_unterminated() {
+  assert_eq!(
+    Params::try_from(&b"foo bar\nmoo cow\n"[..]).unwrap_err(),
+    Error::BadFormat("Missing terminating empty line".to_string())
+  );
+}
+
+
+#[test]
+fn keys_and_values() {
+  let mut params = Params::new();
+  params.add_param("moo", "cow").unwrap();
+  params.add_param("foo", "bar").unwrap();
+  params.add_param("baa", "sheep").unwrap();
+
+  let mut keys: Vec<&str> = params.keys().collect();
+  keys.sort_unstable();
+  assert_eq!(keys, vec!["baa", "foo", "moo"]);
+
+  let mut values: Vec<&str> = params.values().collect();
+  values.sort_unstable();
+  assert_eq!(values, vec!["bar", "cow", "sheep"]);
+}
+
+
+#[test]
+fn into_kvlines_ordered() {
+  let mut params = Params::new();
+  params.add_param("d", "4").unwrap();
+  params.add_param("b", "2").unwrap();
+  params.add_param("a", "1").unwrap();
+  params.add_param("c", "3").unwrap();
+
+  let kvlines = params.clone().into_kvlines_sorted();
+  assert_eq!(kvlines.serialize().unwrap(), b"a 1\nb 2\nc 3\nd 4\n\n");
+
+  let kvlines = params.into_kvlines_with_order(&["c", "x", "a"]);
+  assert_eq!(kvlines.serialize().unwrap(), b"c 3\na 1\nb 2\nd 4\n\n");
+}
+
+
+#[test]
+fn insert_returns_previous() {
+  let mut params = Params::new();
+
+  assert_eq!(params.insert("foo", "bar"), Ok(None));
+  assert_eq!(params.insert("foo", "baz"), Ok(Some("bar".to_string())));
+  assert_eq!(params.get_str("foo"), Some("baz"));
+
+  assert_eq!(
+    params.insert("bad key", "value"),
+    Err(Error::BadFormat("Invalid key character".to_string()))
+  );
+}
+
+
+#[test]
+fn aggregates() {
+  let mut params = Params::new();
+  params.add_param("a", 1).unwrap();
+  params.add_param("b", 20).unwrap();
+  params.add_param("c", 300).unwrap();
+  params.add_param("d", "many").unwrap();
+
+  assert_eq!(params.sum::<u32>(&["a", "b", "c"]), Ok(321));
+  assert_eq!(params.sum::<u32>(&[]), Ok(0));
+  assert_eq!(params.max_value::<i64>(&["a", "c", "b"]), Ok(Some(300)));
+  assert_eq!(params.min_value::<i64>(&["b", "c", "a"]), Ok(Some(1)));
+  assert_eq!(params.max_value::<i64>(&[]), Ok(None));
+
+  assert_eq!(
+    params.sum::<u32>(&["a", "nonexistent"]),
+    Err(Error::KeyNotFound("nonexistent".to_string()))
+  );
+  assert!(matches!(
+    params.sum::<u32>(&["a", "d"]),
+    Err(Error::BadFormat(_))
+  ));
+}
+
+
+#[test]
+fn map_values() {
+  let mut params = Params::new();
+  params.add_str("foo", "bar").unwrap();
+  params.add_str("moo", "cow").unwrap();
+
+  params.map_values(|v| v.to_uppercase());
+
+  assert_eq!(params.len(), 2);
+  assert_eq!(params.get_str("foo"), Some("BAR"));
+  assert_eq!(params.get_str("moo"), Some("COW"));
+}
+
+
+#[test]
+fn merge_with() {
+  let mut params = Params::new();
+  params.add_str("count", "3").unwrap();
+  params.add_str("name", "frank").unwrap();
+
+  let mut other = Params::new();
+  other.add_str("count", "4").unwrap();
+  other.add_str("job", "agent").unwrap();
+
+  params
+    .merge_with(&other, |_k, old, new| {
+      let sum = old.parse::<u32>().unwrap() + new.parse::<u32>().unwrap();
+      sum.to_string()
+    })
+    .unwrap();
+
+  assert_eq!(params.len(), 3);
+  assert_eq!(params.get_int::<u32>("count").unwrap(), 7);
+  assert_eq!(params.get_str("name"), Some("frank"));
+  assert_eq!(params.get_str("job"), Some("agent"));
+}
+
+
+#[test]
+fn filter_map() {
+  let mut params = Params::new();
+  params.add_str("name", "frank").unwrap();
+  params.add_str("password", "secret").unwrap();
+  params.add_param("age", 42).unwrap();
+
+  let out = params
+    .filter_map(|k, v| match k {
+      "password" => None,
+      "age" => Some(("years".to_string(), format!("{}y", v))),
+      _ => Some((k.to_string(), v.to_string()))
+    })
+    .unwrap();
+
+  assert_eq!(out.len(), 2);
+  assert_eq!(out.get_str("name"), Some("frank"));
+  assert_eq!(out.get_str("years"), Some("42y"));
+  assert!(!out.have("password"));
+  assert!(!out.have("age"));
+
+  let res =
+    params.filter_map(|k, v| Some((format!("bad {}", k), v.to_string())));
+  assert!(res.is_err());
+}
+
+
+#[test]
+fn extend() {
+  let mut params = Params::new();
+  params.extend(vec![("foo", "bar"), ("bad key", "skipped"), ("moo", "cow")]);
+  assert_eq!(params.len(), 2);
+  assert_eq!(params.get_str("foo"), Some("bar"));
+  assert_eq!(params.get_str("moo"), Some("cow"));
+
+  params.extend(vec![
+    ("foo".to_string(), "baz".to_string()),
+    ("".to_string(), "skipped".to_string()),
+    ("one".to_string(), "1".to_string())
+  ]);
+  assert_eq!(params.len(), 3);
+  assert_eq!(params.get_str("foo"), Some("baz"));
+  assert_eq!(params.get_str("one"), Some("1"));
+}
+
+
+#[test]
+fn key_policy() {
+  // The default policy allows '.'
+  let mut params = Params::new_with_policy(KeyPolicy::new());
+  params.add_str("user.name", "frank").unwrap();
+  assert_eq!(params.get_str("user.name"), Some("frank"));
+
+  let mut params = Params::new_with_policy(KeyPolicy::new().forbid("."));
+  assert!(matches!(
+    params.add_str("user.name", "frank"),
+    Err(Error::BadFormat(_))
+  ));
+  assert!(params.insert("user.name", "frank").is_err());
+  params.add_str("user_name", "frank").unwrap();
+
+  params.extend(vec![("a.b", "skipped"), ("ab", "added")]);
+  assert_eq!(params.len(), 2);
+  assert!(!params.have("a.b"));
+
+  // The policy survives clearing the buffer
+  params.clear();
+  assert!(params.add_str("user.name", "frank").is_err());
+}
+
+
+#[test]
+fn diff() {
+  let mut old = Params::new();
+  old.add_str("same", "1").unwrap();
+  old.add_str("removed", "2").unwrap();
+  old.add_str("changed", "3").unwrap();
+
+  let mut new = Params::new();
+  new.add_str("same", "1").unwrap();
+  new.add_str("changed", "4").unwrap();
+  new.add_str("added", "5").unwrap();
+
+  let diff = old.diff(&new);
+  assert_eq!(diff.added, vec![("added".to_string(), "5".to_string())]);
+  assert_eq!(diff.removed, vec![("removed".to_string(), "2".to_string())]);
+  assert_eq!(
+    diff.changed,
+    vec![("changed".to_string(), "3".to_string(), "4".to_string())]
+  );
+  assert!(!diff.is_empty());
+
+  assert!(old.diff(&old.clone()).is_empty());
+}
+
+
+#[test]
+fn get_path() {
+  let mut params = Params::new();
+  params.add_str("db.primary.host", "localhost").unwrap();
+  params.add_str("db.primary.port", "5432").unwrap();
+  params.add_str("db.replica.host", "remote").unwrap();
+  params.add_str("dbx.host", "other").unwrap();
+  params.add_str("name", "app").unwrap();
+
+  let db = params.get_path("db");
+  assert_eq!(db.len(), 3);
+  assert_eq!(db.get_str("replica.host"), Some("remote"));
+  assert!(!db.have("host"));
+
+  let primary = db.get_path("primary");
+  assert_eq!(primary.len(), 2);
+  assert_eq!(primary.get_str("host"), Some("localhost"));
+  assert_eq!(primary.get_int::<u16>("port").unwrap(), 5432);
+
+  let primary = params.get_path("db.primary");
+  assert_eq!(primary.param_names(), vec!["host", "port"]);
+  assert!(params.get_path("nonexist").is_empty());
+  assert_eq!(params.get_path("").len(), 5);
+}
+
+
+#[test]
+fn get_path_deep() {
+  let segments = vec!["a"; 10_000];
+  let key = segments.join(".");
+
+  let mut params = Params::new();
+  params.add_str(&key, "deep").unwrap();
+
+  let leaf = params.get_path(&segments[..9_999].join("."));
+  assert_eq!(leaf.param_names(), vec!["a"]);
+  assert_eq!(leaf.get_str("a"), Some("deep"));
+
+  let sub = params.get_path("a");
+  assert_eq!(sub.get_str(&segments[1..].join(".")), Some("deep"));
+  assert!(params.get_path(&key).is_empty());
+}
+
+
+#[test]
+fn entry() {
+  let mut params = Params::new();
+  params.add_str("existing", "old").unwrap();
+
+  let v = params.entry("missing".to_string()).unwrap().or_insert("new");
+  assert_eq!(v, "new");
+  v.push_str("er");
+  assert_eq!(params.get_str("missing"), Some("newer"));
+
+  let v = params.entry("existing".to_string()).unwrap().or_insert("new");
+  assert_eq!(v, "old");
+
+  params
+    .entry("existing".to_string())
+    .unwrap()
+    .and_modify(|v| v.make_ascii_uppercase())
+    .or_insert("new");
+  assert_eq!(params.get_str("existing"), Some("OLD"));
+
+  assert!(matches!(
+    params.entry("bad key".to_string()),
+    Err(Error::BadFormat(_))
+  ));
+  assert_eq!(params.len(), 2);
+}
+
+
+#[test]
+fn subset() {
+  let mut required = Params::new();
+  required.add_str("Name", "Frank").unwrap();
+  required.add_str("Mode", "fast").unwrap();
+
+  let mut req = required.clone();
+  req.add_str("Extra", "1").unwrap();
+  assert!(required.is_subset_of(&req));
+  assert!(required.is_subset_of(&required));
+  assert!(Params::new().is_subset_of(&req));
+  assert!(!req.is_subset_of(&required));
+
+  // Value mismatch
+  let mut mismatch = req.clone();
+  mismatch.add_str("Mode", "slow").unwrap();
+  assert!(!required.is_subset_of(&mismatch));
+
+  // Missing key
+  let mut missing = req.clone();
+  missing.retain(|k, _| k != "Name");
+  assert!(!required.is_subset_of(&missing));
+}
+
+
+#[test]
+fn contains_all_keys() {
+  let mut params = Params::new();
+  params.add_str("Name", "Frank").unwrap();
+  params.add_str("Age", "42").unwrap();
+
+  assert!(params.contains_all_keys(&["Name", "Age"]));
+  assert!(params.contains_all_keys(&[]));
+  assert!(!params.contains_all_keys(&["Name", "Email"]));
+}
+
+
+#[test]
+fn set_param_if_absent() {
+  let mut params = Params::new();
+  params.add_param("Timeout", 10).unwrap();
+
+  assert_eq!(params.set_param_if_absent("Timeout", 30), Ok(false));
+  assert_eq!(params.set_param_if_absent("Retries", 3), Ok(true));
+  assert_eq!(params.set_param_if_absent("Retries", 5), Ok(false));
+  assert_eq!(params.get_int::<u32>("Timeout").unwrap(), 10);
+  assert_eq!(params.get_int::<u32>("Retries").unwrap(), 3);
+
+  assert!(matches!(
+    params.set_param_if_absent("bad key", "x"),
+    Err(Error::BadFormat(_))
+  ));
+}
+
+
+#[test]
+fn clear_and_reserve() {
+  let mut params = Params::new();
+  for i in 0..8 {
+    params.add_param(format!("key{}", i), i).unwrap();
+  }
+  let cap = params.capacity();
+  assert!(cap >= 8);
+
+  params.clear();
+  assert!(params.is_empty());
+  assert_eq!(params.capacity(), cap);
+
+  params.reserve(cap + 16);
+  let cap = params.capacity();
+  assert!(cap >= 16);
+  for i in 0..16 {
+    params.add_param(format!("key{}", i), i).unwrap();
+  }
+  assert_eq!(params.len(), 16);
+  assert_eq!(params.capacity(), cap);
+  assert_eq!(params.get_int::<u32>("key15").unwrap(), 15);
+}
+
+
+#[test]
+fn ip_addrs() {
+  let mut params = Params::new();
+  params.add_str("v4", "10.0.0.1").unwrap();
+  params.add_str("v6", "::1").unwrap();
+  params.add_str("sa4", "10.0.0.1:8080").unwrap();
+  params.add_str("sa6", "[::1]:443").unwrap();
+  params.add_str("bad", "10.0.0.256").unwrap();
+
+  assert_eq!(
+    params.get_ip("v4").unwrap(),
+    IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))
+  );
+  assert_eq!(params.get_ip("v6").unwrap(), IpAddr::V6(Ipv6Addr::LOCALHOST));
+  assert_eq!(
+    params.get_socket_addr("sa4").unwrap(),
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 8080)
+  );
+  assert_eq!(
+    params.get_socket_addr("sa6").unwrap(),
+    SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 443)
+  );
+
+  assert!(matches!(params.get_ip("bad"), Err(Error::BadFormat(_))));
+  assert!(matches!(params.get_socket_addr("v4"), Err(Error::BadFormat(_))));
+  assert_eq!(
+    params.get_ip("nonexist"),
+    Err(Error::KeyNotFound("nonexist".to_string()))
+  );
+}
+
+
+#[test]
+fn is_valid() {
+  let mut params = Params::new();
+  params.add_str("name", "frank").unwrap();
+  assert!(params.is_valid());
+  assert!(Params::new().is_valid());
+
+  let mut hm = HashMap::new();
+  hm.insert("name".to_string(), "frank".to_string());
+  hm.insert("bad key".to_string(), "value".to_string());
+  assert!(!Params::from(hm).is_valid());
+}
+
+
+#[test]
+fn try_from_map() {
+  let mut hm = HashMap::new();
+  hm.insert("name".to_string(), "frank".to_string());
+  let params = Params::try_from_map(hm.clone()).unwrap();
+  assert_eq!(params.len(), 1);
+  assert_eq!(params.get_str("name"), Some("frank"));
+
+  hm.insert("bad key".to_string(), "value".to_string());
+  assert!(matches!(Params::try_from_map(hm), Err(Error::BadFormat(_))));
+
+  let mut hm = HashMap::new();
+  hm.insert("line\nbreak".to_string(), "value".to_string());
+  assert!(matches!(Params::try_from_map(hm), Err(Error::BadFormat(_))));
+}
+
+
+#[test]
+fn heap_size() {
+  let mut params = Params::new();
+  assert_eq!(params.sum_values_len(), 0);
+
+  let mut size = params.approx_heap_size();
+  for i in 0..32 {
+    params.add_param(format!("key{}", i), "x".repeat(i + 1)).unwrap();
+    let new_size = params.approx_heap_size();
+    assert!(new_size > size);
+    size = new_size;
+  }
+  assert_eq!(params.sum_values_len(), (1..=32).sum::<usize>());
+}
+
+
+#[derive(Debug, PartialEq)]
+enum Level {
+  Low,
+  High
+}
+
+impl FromStr for Level {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, ()> {
+    match s {
+      "low" => Ok(Level::Low),
+      "high" => Ok(Level::High),
+      _ => Err(())
+    }
+  }
+}
+
+#[test]
+fn get_enum() {
+  let mut params = Params::new();
+  params.add_str("good", "high").unwrap();
+  params.add_str("bad", "medium").unwrap();
+
+  let allowed = ["low", "high"];
+  assert_eq!(params.get_enum::<Level>("good", &allowed), Ok(Level::High));
+  match params.get_enum::<Level>("bad", &allowed) {
+    Err(Error::BadFormat(msg)) => {
+      assert!(msg.contains("medium"));
+      assert!(msg.contains("low, high"));
+    }
+    _ => panic!("Expected a BadFormat error")
+  }
+  assert_eq!(
+    params.get_enum::<Level>("nonexist", &allowed),
+    Err(Error::KeyNotFound("nonexist".to_string()))
+  );
+}
+
+
+#[test]
+fn drain() {
+  let mut params = Params::new();
+  for i in 0..8 {
+    params.add_param(format!("key{}", i), i).unwrap();
+  }
+  let cap = params.capacity();
+
+  let mut pairs: Vec<(String, String)> = params.drain().collect();
+  pairs.sort();
+  assert_eq!(pairs.len(), 8);
+  assert_eq!(pairs[0], ("key0".to_string(), "0".to_string()));
+  assert_eq!(pairs[7], ("key7".to_string(), "7".to_string()));
+
+  assert!(params.is_empty());
+  assert_eq!(params.capacity(), cap);
+
+  params.add_str("reused", "yes").unwrap();
+  assert_eq!(params.len(), 1);
+  assert_eq!(params.get_str("reused"), Some("yes"));
+  assert_eq!(params.capacity(), cap);
+}
+
+
 // vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
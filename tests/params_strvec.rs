@@ -1,3 +1,5 @@
+#![allow(clippy::vec_init_then_push)]
+
 use std::collections::HashSet;
 
 use blather::Params;
@@ -110,4 +112,21 @@ fn add_hashset() {
 }
 
 
+#[test]
+fn strvec_alt_separator() {
+  let mut params = Params::new();
+
+  let v = vec!["Doe, Jane", "Smith, John"];
+  params.add_strit_with("names", &v, '|').unwrap();
+  assert_eq!(params.get_str("names"), Some("Doe, Jane|Smith, John"));
+
+  let sv = params.get_strvec_with("names", '|').unwrap();
+  assert_eq!(sv, v);
+
+  // Comma-separated parsing splits the values
+  let sv = params.get_strvec("names").unwrap();
+  assert_eq!(sv.len(), 3);
+}
+
+
 // vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
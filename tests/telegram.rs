@@ -1,4 +1,6 @@
-use blather::{Error, Telegram};
+use std::collections::HashSet;
+
+use blather::{Error, KVLines, LineEnding, Params, Telegram};
 
 #[test]
 fn simple() {
@@ -19,9 +21,9 @@ fn exist() {
   let mut tg = Telegram::new();
 
   tg.add_str("foo", "bar").unwrap();
-  assert_eq!(tg.have_param("foo"), true);
+  assert!(tg.have_param("foo"));
 
-  assert_eq!(tg.have_param("nonexistent"), false);
+  assert!(!tg.have_param("nonexistent"));
 }
 
 
@@ -41,7 +43,7 @@ fn integer() {
 fn size() {
   let mut msg = Telegram::new();
 
-  msg.add_param("Num", 7 as usize).unwrap();
+  msg.add_param("Num", 7_usize).unwrap();
   assert_eq!(msg.get_int::<usize>("Num").unwrap(), 7);
 }
 
@@ -117,4 +119,507 @@ fn bad_topic() {
 }
 
 
+#[test]
+fn unknown_params() {
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  tg.add_param("allowed", "yes").unwrap();
+  tg.add_param("disallowed", "no").unwrap();
+
+  let mut allowed = HashSet::new();
+  allowed.insert("allowed");
+
+  assert_eq!(tg.unknown_params(&allowed), vec!["disallowed"]);
+
+  allowed.insert("disallowed");
+  assert!(tg.unknown_params(&allowed).is_empty());
+}
+
+
+#[test]
+fn unchecked_topic() {
+  let tg = Telegram::new_topic_unchecked("Some Topic".to_string());
+  assert_eq!(tg.get_topic(), Some("Some Topic"));
+
+  let mut tg = Telegram::new();
+  tg.set_topic_unchecked(" SomeTopic".to_string());
+  assert_eq!(tg.get_topic(), Some(" SomeTopic"));
+}
+
+
+#[test]
+fn merge_params() {
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  tg.add_param("foo", "bar").unwrap();
+  tg.add_param("moo", "cow").unwrap();
+
+  let mut other = Telegram::new_topic("hello").unwrap();
+  other.add_param("moo", "bull").unwrap();
+  other.add_param("baa", "sheep").unwrap();
+
+  tg.merge_params(&other).unwrap();
+
+  assert_eq!(tg.num_params(), 3);
+  assert_eq!(tg.get_str("foo"), Some("bar"));
+  assert_eq!(tg.get_str("moo"), Some("bull"));
+  assert_eq!(tg.get_str("baa"), Some("sheep"));
+}
+
+
+#[test]
+fn merge_params_topic_mismatch() {
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  let mut other = Telegram::new_topic("goodbye").unwrap();
+  other.add_param("foo", "bar").unwrap();
+
+  assert_eq!(
+    tg.merge_params(&other),
+    Err(Error::BadState("Telegram topics differ".to_string()))
+  );
+  assert_eq!(tg.num_params(), 0);
+
+  let mut untitled = Telegram::new();
+  assert_eq!(
+    untitled.merge_params(&other),
+    Err(Error::BadState("Missing Telegram topic".to_string()))
+  );
+}
+
+
+#[test]
+fn param_names() {
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  tg.add_param("moo", "cow").unwrap();
+  tg.add_param("baa", "sheep").unwrap();
+  tg.add_param("foo", "bar").unwrap();
+
+  assert_eq!(tg.param_names(), vec!["baa", "foo", "moo"]);
+}
+
+
+#[test]
+fn topic_matches() {
+  let tg = Telegram::new_topic("hello").unwrap();
+  assert!(tg.topic_matches("hello"));
+  assert!(!tg.topic_matches("hell"));
+  assert!(tg.topic_matches("*"));
+  assert!(tg.topic_matches("**"));
+  assert!(!tg.topic_matches("*/*"));
+
+  let tg = Telegram::new_topic_unchecked("user/frank/age".to_string());
+  assert!(tg.topic_matches("user/frank/age"));
+  assert!(!tg.topic_matches("user/frank"));
+  assert!(tg.topic_matches("user/*/age"));
+  assert!(!tg.topic_matches("user/*"));
+  assert!(tg.topic_matches("user/**"));
+  assert!(tg.topic_matches("user/frank/age/**"));
+  assert!(tg.topic_matches("*/frank/**"));
+  assert!(!tg.topic_matches("group/**"));
+
+  assert!(!Telegram::new().topic_matches("**"));
+}
+
+
+#[test]
+fn deref_params() {
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  tg.add_param("foo", "bar").unwrap();
+
+  // Params-only methods
+  assert!(!tg.is_empty());
+  assert_eq!(tg.keys().collect::<Vec<_>>(), vec!["foo"]);
+  assert_eq!(tg.insert("foo", "baz"), Ok(Some("bar".to_string())));
+
+  fn count(params: &Params) -> usize {
+    params.len()
+  }
+  assert_eq!(count(&tg), 1);
+  assert_eq!(AsRef::<Params>::as_ref(&tg).get_str("foo"), Some("baz"));
+
+  // Telegram's clear() also clears the topic
+  tg.clear();
+  assert_eq!(tg.get_topic(), None);
+}
+
+
+#[test]
+fn deref_resolution() {
+  // len() and is_empty() are Params methods, which ignore the topic
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  assert!(tg.has_topic());
+  assert!(tg.is_empty());
+  assert_eq!(tg.len(), 0);
+
+  // Telegram::wire_size() includes the topic line and returns a Result,
+  // while the shadowed Params::wire_size() does not.
+  tg.add_param("foo", "bar").unwrap();
+  assert_eq!(tg.wire_size().unwrap(), 15);
+  assert_eq!((*tg).wire_size(), 9);
+  assert_eq!(tg.get_params().wire_size(), 9);
+  assert_eq!(tg.calc_buf_size(), 15);
+  assert!(Telegram::new().wire_size().is_err());
+}
+
+
+#[test]
+fn serialize_into() {
+  let mut tg1 = Telegram::new_topic("first").unwrap();
+  tg1.add_param("foo", "bar").unwrap();
+  let tg2 = Telegram::new_topic("second").unwrap();
+
+  let mut buf = Vec::new();
+  tg1.serialize_into(&mut buf).unwrap();
+  tg2.serialize_into(&mut buf).unwrap();
+
+  let mut expected = tg1.serialize().unwrap();
+  expected.extend(tg2.serialize().unwrap());
+  assert_eq!(buf, expected);
+  assert_eq!(buf, b"first\nfoo bar\n\nsecond\n\n");
+
+  // A Telegram without a topic leaves the buffer untouched
+  assert!(Telegram::new().serialize_into(&mut buf).is_err());
+  assert_eq!(buf.len(), expected.len());
+}
+
+
+#[test]
+fn into_topic_and_params() {
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  tg.add_param("foo", "bar").unwrap();
+
+  let (topic, params) = tg.into_topic_and_params();
+  assert_eq!(topic, Some("hello".to_string()));
+  assert_eq!(params.get_str("foo"), Some("bar"));
+
+  let (topic, params) = Telegram::new().into_topic_and_params();
+  assert_eq!(topic, None);
+  assert!(params.is_empty());
+}
+
+
+#[test]
+fn clone_topic() {
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  tg.add_param("foo", "bar").unwrap();
+
+  let tg2 = tg.clone_topic().unwrap();
+  assert_eq!(tg2.get_topic(), Some("hello"));
+  assert_eq!(tg2.num_params(), 0);
+  assert_eq!(tg.num_params(), 1);
+
+  assert!(matches!(
+    Telegram::new().clone_topic(),
+    Err(Error::BadState(_))
+  ));
+}
+
+
+#[test]
+fn get_param_or() {
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  tg.add_param("num", 42).unwrap();
+  tg.add_param("bad", "x").unwrap();
+
+  let val: u32 = tg.get_param_or("num", || panic!("Closure called")).unwrap();
+  assert_eq!(val, 42);
+
+  let mut called = false;
+  let val = tg.get_param_or::<u32, _>("nonexist", || {
+    called = true;
+    11
+  });
+  assert_eq!(val, Ok(11));
+  assert!(called);
+
+  assert!(matches!(
+    tg.get_param_or::<u32, _>("bad", || 11),
+    Err(Error::BadFormat(_))
+  ));
+
+  let params = tg.into_params();
+  let val: u32 =
+    params.get_param_or("num", || panic!("Closure called")).unwrap();
+  assert_eq!(val, 42);
+  assert_eq!(params.get_param_or::<u32, _>("nonexist", || 7), Ok(7));
+}
+
+
+#[test]
+fn retain_params() {
+  let mut tg = Telegram::new_topic("Login").unwrap();
+  tg.add_param("user", "frank").unwrap();
+  tg.add_param("secret", "hunter2").unwrap();
+  tg.add_param("api_secret_key", "abc").unwrap();
+
+  tg.retain_params(|k, _| !k.contains("secret"));
+
+  assert_eq!(tg.param_names(), vec!["user"]);
+  assert_eq!(tg.get_str("user"), Some("frank"));
+  assert_eq!(tg.get_topic(), Some("Login"));
+}
+
+
+#[test]
+fn from_str() {
+  let tg: Telegram = "hello\nfoo bar\nmoo cow\n\n".parse().unwrap();
+  assert_eq!(tg.get_topic(), Some("hello"));
+  assert_eq!(tg.get_str("foo"), Some("bar"));
+  assert_eq!(tg.get_str("moo"), Some("cow"));
+
+  // The terminating empty line and final newline are optional
+  let tg: Telegram = "hello\r\nfoo bar".parse().unwrap();
+  assert_eq!(tg.get_topic(), Some("hello"));
+  assert_eq!(tg.get_str("foo"), Some("bar"));
+
+  assert!(matches!(
+    "hello\nfoo\u{1} bar\n".parse::<Telegram>(),
+    Err(Error::BadFormat(_))
+  ));
+  assert!(matches!(
+    "1hello\n".parse::<Telegram>(),
+    Err(Error::BadFormat(_))
+  ));
+  assert!(matches!("".parse::<Telegram>(), Err(Error::BadFormat(_))));
+  assert!(matches!(
+    "hello\n\nworld\n".parse::<Telegram>(),
+    Err(Error::BadFormat(_))
+  ));
+}
+
+
+#[test]
+fn wire_size() {
+  let tg = Telegram::new_topic("hello").unwrap();
+  assert_eq!(tg.wire_size(), Ok(tg.serialize().unwrap().len()));
+  assert_eq!(tg.wire_size(), Ok(7));
+
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  tg.add_param("foo", "bar").unwrap();
+  tg.add_param("name", "Björn").unwrap();
+  tg.add_param("empty", "").unwrap();
+  assert_eq!(tg.wire_size(), Ok(tg.serialize().unwrap().len()));
+
+  let params = tg.get_params();
+  assert_eq!(params.wire_size(), params.serialize().unwrap().len());
+
+  assert!(matches!(
+    Telegram::new().wire_size(),
+    Err(Error::SerializeError(_))
+  ));
+  assert_eq!(Params::new().wire_size(), 1);
+}
+
+
+#[test]
+fn set_param_if_absent() {
+  let mut tg = Telegram::new_topic("Connect").unwrap();
+  tg.add_param("Port", 8080).unwrap();
+
+  // Apply defaults
+  assert_eq!(tg.set_param_if_absent("Port", 80), Ok(false));
+  assert_eq!(tg.set_param_if_absent("Host", "localhost"), Ok(true));
+  assert_eq!(tg.get_int::<u16>("Port").unwrap(), 8080);
+  assert_eq!(tg.get_str("Host"), Some("localhost"));
+  assert_eq!(tg.num_params(), 2);
+
+  assert!(matches!(
+    tg.set_param_if_absent("bad key", "x"),
+    Err(Error::BadFormat(_))
+  ));
+  assert_eq!(tg.num_params(), 2);
+}
+
+
+#[test]
+fn strvec_def() {
+  let mut tg = Telegram::new_topic("Users").unwrap();
+  tg.add_param("empty", "").unwrap();
+  tg.add_param("names", "elena,chloe").unwrap();
+  let def = || vec!["drake".to_string()];
+
+  assert_eq!(tg.get_strvec_def("missing", def()).unwrap(), def());
+  assert!(tg.get_strvec_def("empty", def()).unwrap().is_empty());
+  assert_eq!(
+    tg.get_strvec_def("names", def()).unwrap(),
+    vec!["elena", "chloe"]
+  );
+}
+
+
+#[test]
+fn hashset_def() {
+  let mut tg = Telegram::new_topic("Users").unwrap();
+  tg.add_param("empty", "").unwrap();
+  tg.add_param("names", "elena,chloe,elena").unwrap();
+  let def = || {
+    let mut hs = HashSet::new();
+    hs.insert("drake".to_string());
+    hs
+  };
+
+  assert_eq!(tg.get_hashset_def("missing", def()).unwrap(), def());
+  assert!(tg.get_hashset_def("empty", def()).unwrap().is_empty());
+  let hs = tg.get_hashset_def("names", def()).unwrap();
+  assert_eq!(hs.len(), 2);
+  assert!(hs.contains("elena"));
+  assert!(hs.contains("chloe"));
+}
+
+
+#[test]
+fn serialize_crlf() {
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  tg.add_param("foo", "bar").unwrap();
+  assert_eq!(
+    tg.serialize_with(LineEnding::CrLf).unwrap(),
+    b"hello\r\nfoo bar\r\n\r\n"
+  );
+  assert_eq!(
+    tg.serialize_with(LineEnding::Lf).unwrap(),
+    tg.serialize().unwrap()
+  );
+
+  let mut params = Params::new();
+  params.add_param("foo", "bar").unwrap();
+  assert_eq!(
+    params.serialize_with(LineEnding::CrLf).unwrap(),
+    b"foo bar\r\n\r\n"
+  );
+
+  let mut kvlines = KVLines::new();
+  kvlines.append("foo", "bar");
+  kvlines.append("foo", "baz");
+  assert_eq!(
+    kvlines.serialize_with(LineEnding::CrLf).unwrap(),
+    b"foo bar\r\nfoo baz\r\n\r\n"
+  );
+
+  // Serialized buffers can be parsed back
+  let buf = tg.serialize_with(LineEnding::CrLf).unwrap();
+  let tg2: Telegram = std::str::from_utf8(&buf).unwrap().parse().unwrap();
+  assert_eq!(tg2.get_topic(), Some("hello"));
+  assert_eq!(tg2.get_str("foo"), Some("bar"));
+}
+
+
+#[test]
+fn topic_owned() {
+  let id = 7;
+  let topic = format!("Job{}", id);
+  let ptr = topic.as_ptr();
+  let mut tg = Telegram::new_topic_owned(topic).unwrap();
+  assert_eq!(tg.get_topic(), Some("Job7"));
+  assert_eq!(tg.get_topic().unwrap().as_ptr(), ptr);
+
+  tg.set_topic_owned(format!("Task{}", id)).unwrap();
+  assert_eq!(tg.get_topic(), Some("Task7"));
+
+  assert!(matches!(
+    Telegram::new_topic_owned(format!("Bad {}", id)),
+    Err(Error::BadFormat(_))
+  ));
+  assert!(matches!(
+    tg.set_topic_owned(String::new()),
+    Err(Error::BadFormat(_))
+  ));
+  assert_eq!(tg.get_topic(), Some("Task7"));
+}
+
+
+#[test]
+fn eq_ignoring() {
+  let mut a = Telegram::new_topic("Reply").unwrap();
+  a.add_param("Status", "ok").unwrap();
+  a.add_param("ReqId", 1).unwrap();
+  a.add_param("Time", 1000).unwrap();
+
+  let mut b = Telegram::new_topic("Reply").unwrap();
+  b.add_param("Status", "ok").unwrap();
+  b.add_param("ReqId", 2).unwrap();
+
+  assert!(a.eq_ignoring(&b, &["ReqId", "Time"]));
+  assert!(b.eq_ignoring(&a, &["ReqId", "Time"]));
+  assert!(!a.eq_ignoring(&b, &["ReqId"]));
+  assert!(!a.eq_ignoring(&b, &["Time"]));
+
+  b.add_param("Status", "failed").unwrap();
+  assert!(!a.eq_ignoring(&b, &["ReqId", "Time"]));
+
+  let mut c = a.clone();
+  c.set_topic("Other").unwrap();
+  assert!(!a.eq_ignoring(&c, &[]));
+  assert!(a.eq_ignoring(&a.clone(), &[]));
+}
+
+
+#[test]
+fn has_topic() {
+  let mut tg = Telegram::new();
+  assert!(!tg.has_topic());
+  tg.set_topic("Hello").unwrap();
+  assert!(tg.has_topic());
+  tg.clear();
+  assert!(!tg.has_topic());
+}
+
+
+#[test]
+fn heap_size() {
+  let mut tg = Telegram::new();
+  let empty = tg.approx_heap_size();
+
+  tg.set_topic("Hello").unwrap();
+  let with_topic = tg.approx_heap_size();
+  assert!(with_topic > empty);
+
+  tg.add_param("Name", "Frank").unwrap();
+  assert!(tg.approx_heap_size() > with_topic);
+}
+
+
+#[test]
+fn serialize_sorted() {
+  let mut a = Telegram::new_topic("User").unwrap();
+  for (k, v) in &[("name", "frank"), ("age", "42"), ("job", "agent")] {
+    a.add_param(k, v).unwrap();
+  }
+
+  let mut b = Telegram::new_topic("User").unwrap();
+  for (k, v) in &[("job", "agent"), ("name", "frank"), ("age", "42")] {
+    b.add_param(k, v).unwrap();
+  }
+
+  assert_eq!(a.entries_sorted(), b.entries_sorted());
+  assert_eq!(
+    a.entries_sorted(),
+    vec![("age", "42"), ("job", "agent"), ("name", "frank")]
+  );
+  assert_eq!(a.serialize_sorted().unwrap(), b.serialize_sorted().unwrap());
+  assert_eq!(
+    a.serialize_sorted().unwrap(),
+    b"User\nage 42\njob agent\nname frank\n\n"
+  );
+
+  assert!(Telegram::new().serialize_sorted().is_err());
+}
+
+
+#[test]
+fn to_bytes() {
+  let mut tg = Telegram::new_topic("hello").unwrap();
+  tg.add_param("foo", "bar").unwrap();
+  tg.add_param("moo", "cow").unwrap();
+  assert_eq!(&tg.to_bytes().unwrap()[..], &tg.serialize().unwrap()[..]);
+  assert!(Telegram::new().to_bytes().is_err());
+
+  let params = tg.into_params();
+  assert_eq!(
+    &params.to_bytes().unwrap()[..],
+    &params.serialize().unwrap()[..]
+  );
+
+  let kvlines = KVLines::builder().line("a", "1").line("a", "2").build();
+  assert_eq!(&kvlines.to_bytes().unwrap()[..], b"a 1\na 2\n\n");
+}
+
+
 // vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -1,10 +1,13 @@
+#![cfg(feature = "codec")]
+#![allow(clippy::never_loop)]
+
 use tokio_stream::StreamExt;
 
 use tokio_test::io::Builder;
 
 use tokio_util::codec::Framed;
 
-use blather::{codec, Codec};
+use blather::{codec, Codec, Error};
 
 #[tokio::test]
 async fn tg_followed_by_buf() {
@@ -35,4 +38,70 @@ async fn tg_followed_by_buf() {
   }
 }
 
+#[tokio::test]
+async fn truncated_file() {
+  let mut mock = Builder::new();
+
+  mock.read(b"hello\nlen 8\n\n1234");
+
+  let mut frm = Framed::new(mock.build(), Codec::new());
+
+  let pathname = std::env::temp_dir().join("blather-truncated_file.bin");
+
+  if let Some(o) = frm.next().await {
+    let o = o.unwrap();
+    if let codec::Input::Telegram(tg) = o {
+      let len = tg.get_int::<usize>("len").unwrap();
+      frm.codec_mut().expect_file(&pathname, len).unwrap();
+    } else {
+      panic!("Not a Telegram");
+    }
+  }
+
+  match frm.next().await {
+    Some(Err(Error::Incomplete(_))) => {}
+    _ => panic!("Expected an Incomplete error")
+  }
+
+  let _ = std::fs::remove_file(&pathname);
+}
+
+
+#[tokio::test]
+async fn chunks_until_eof() {
+  let mut mock = Builder::new();
+
+  mock.read(b"hello\n\n");
+  mock.read(b"1234");
+  mock.read(b"5678");
+  mock.read(b"9");
+
+  let mut frm = Framed::new(mock.build(), Codec::new());
+
+  if let Some(o) = frm.next().await {
+    let o = o.unwrap();
+    if let codec::Input::Telegram(tg) = o {
+      assert_eq!(tg.get_topic(), Some("hello"));
+      frm.codec_mut().expect_chunks_until_eof();
+    } else {
+      panic!("Not a Telegram");
+    }
+  }
+
+  let mut data = Vec::new();
+  let mut nchunks = 0;
+  while let Some(o) = frm.next().await {
+    if let codec::Input::RawChunk(chunk) = o.unwrap() {
+      data.extend_from_slice(&chunk);
+      nchunks += 1;
+    } else {
+      panic!("Not a RawChunk");
+    }
+  }
+
+  assert_eq!(data, b"123456789");
+  assert!(nchunks >= 1);
+}
+
+
 // vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
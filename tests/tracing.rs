@@ -0,0 +1,44 @@
+#![cfg(feature = "tracing")]
+
+use bytes::BytesMut;
+
+use tokio_util::codec::Decoder;
+
+use tracing_test::traced_test;
+
+use blather::{codec, Codec};
+
+#[traced_test]
+#[test]
+fn telegram_received() {
+  let mut codec = Codec::new();
+
+  let mut buf = BytesMut::from(&b"hello\nfoo bar\n\n"[..]);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Telegram(tg)) => {
+      assert_eq!(tg.get_topic(), Some("hello"));
+    }
+    _ => panic!("Not a Telegram")
+  }
+
+  assert!(logs_contain("Telegram received"));
+  assert!(logs_contain("topic=\"hello\""));
+}
+
+
+#[traced_test]
+#[test]
+fn binary_transfer() {
+  let mut codec = Codec::new();
+  codec.expect_bytes(4).unwrap();
+  assert!(logs_contain("Binary transfer started"));
+
+  let mut buf = BytesMut::from(&b"1234"[..]);
+  match codec.decode(&mut buf).unwrap() {
+    Some(codec::Input::Bytes(_)) => {}
+    _ => panic!("Not a Bytes")
+  }
+  assert!(logs_contain("Binary transfer ended"));
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :